@@ -1,10 +1,12 @@
-use color_eyre::Report;
-use std::sync::Arc;
-use std::{io, net::SocketAddr};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+use color_eyre::{eyre::eyre, Report};
+use httplib::{
+    dns::{self, SystemResolver},
+    http1,
+    redirect::RedirectPolicy,
 };
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_rustls::{
     rustls::{self, OwnedTrustAnchor},
     TlsConnector,
@@ -12,9 +14,6 @@ use tokio_rustls::{
 use tracing::info;
 
 pub async fn fetch_thing(name: &str) -> Result<(), Report> {
-    let addr: SocketAddr = ([1, 1, 1, 1], 443).into();
-    let socket = TcpStream::connect(addr).await?;
-
     let mut root_cert_store = rustls::RootCertStore::empty();
     root_cert_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
         OwnedTrustAnchor::from_subject_spki_name_constraints(
@@ -29,22 +28,50 @@ pub async fn fetch_thing(name: &str) -> Result<(), Report> {
         .with_no_client_auth();
     let connector = TlsConnector::from(Arc::new(config));
 
-    let domain = rustls::ServerName::try_from("one.one.one.one")
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid dnsname"))?;
+    let mut redirects =
+        RedirectPolicy::default().start("GET", "https://one.one.one.one/".parse()?)?;
+    loop {
+        let url = redirects.url().clone();
+        if url.scheme_str() != Some("https") {
+            return Err(eyre!("refusing to follow redirect to {url}"));
+        }
+        let host = url.host().unwrap_or_default();
+        let port = url.port_u16().unwrap_or(443);
+        let socket = dns::connect(&SystemResolver, host, port).await?;
+
+        let domain = rustls::ServerName::try_from(host)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid dnsname"))?;
 
-    let mut socket = connector.connect(domain, socket).await?;
+        let mut socket = connector.connect(domain, socket).await?;
 
-    socket.write_all(b"GET / HTTP/1.1\r\n").await?;
-    socket.write_all(b"Host: one.one.one.one\r\n").await?;
-    socket.write_all(b"User-Agent: cool-bear\r\n").await?;
-    socket.write_all(b"Connection: close\r\n").await?;
-    socket.write_all(b"\r\n").await?;
+        let target = url.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let authority = url.authority().map(|a| a.as_str()).unwrap_or_default();
+        socket
+            .write_all(format!("{} {target} HTTP/1.1\r\n", redirects.method()).as_bytes())
+            .await?;
+        socket
+            .write_all(format!("Host: {authority}\r\n").as_bytes())
+            .await?;
+        socket.write_all(b"User-Agent: cool-bear\r\n").await?;
+        socket.write_all(b"Connection: close\r\n").await?;
+        socket.write_all(b"\r\n").await?;
 
-    let mut response = String::with_capacity(256);
-    socket.read_to_string(&mut response).await?;
+        let mut response = Vec::with_capacity(256);
+        socket.read_to_end(&mut response).await?;
 
-    let status = response.lines().next().unwrap_or_default();
-    info!(%status, %name, "Got response!");
+        let (_, res) =
+            http1::response(&response).map_err(|e| eyre!("invalid response from {url}: {e:?}"))?;
+        if redirects.follow(res.status, res.location())? {
+            info!(%name, "Redirected to {}", redirects.url());
+            continue;
+        }
 
-    Ok(())
+        let status = format!(
+            "{} {}",
+            res.status,
+            String::from_utf8_lossy(res.status_text)
+        );
+        info!(%status, %name, "Got response!");
+        return Ok(());
+    }
 }
@@ -1,19 +1,22 @@
 use color_eyre::eyre::eyre;
-use nom::Offset;
 use rustls::{Certificate, ClientConfig, KeyLogFile, RootCertStore};
 use std::{str::FromStr, sync::Arc};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-};
-use tokio_rustls::TlsConnector;
+use tokio::net::TcpStream;
+use tokio_rustls::{client::TlsStream, TlsConnector};
 use tracing::info;
 use tracing_subscriber::{filter::targets::Targets, layer::SubscriberExt, util::SubscriberInitExt};
 
-use std::net::ToSocketAddrs;
+use std::time::Duration;
 use tokio::time::Instant;
 
-use httplib::http1;
+use httplib::{
+    connect::{happy_eyeballs, DEFAULT_ATTEMPT_DELAY},
+    dns::{CachingResolver, Resolver, SystemResolver},
+    encoding, http1,
+    proxy::HttpProxy,
+    redirect::RedirectPolicy,
+    timings::RequestTimings,
+};
 
 fn setup() -> color_eyre::Result<()> {
     color_eyre::install().unwrap();
@@ -44,93 +47,143 @@ fn set_tls_connector() -> color_eyre::Result<TlsConnector> {
     Ok(connector)
 }
 
-#[tokio::main]
-async fn main() -> color_eyre::Result<()> {
-    setup()?;
-
-    let connector = set_tls_connector()?;
-
-    let before = Instant::now();
-    let addr = "example.org:443"
-        .to_socket_addrs()?
-        .next()
-        .ok_or_else(|| eyre!("Failed to resolve address for example.org:443"))?;
-    info!("{:?} DNS lookup", before.elapsed());
-
-    let before = Instant::now();
-    let stream = TcpStream::connect(addr).await?;
-    stream.set_nodelay(true)?;
-    info!("{:?} TCP connect", before.elapsed());
+/// Connects to `host` (a URL authority, with an optional port) over TLS,
+/// through the proxy in `HTTPS_PROXY` if it's set. How long that took is
+/// reported with the first request.
+async fn connect(
+    connector: &TlsConnector,
+    resolver: &dyn Resolver,
+    host: &str,
+) -> color_eyre::Result<http1::ClientConnection<TlsStream<TcpStream>>> {
+    let authority: http::uri::Authority = host.parse()?;
+    let port = authority.port_u16().unwrap_or(443);
+
+    let proxy = std::env::var("HTTPS_PROXY")
+        .or_else(|_| std::env::var("https_proxy"))
+        .ok();
+    let mut timings = RequestTimings::default();
+    let stream = if let Some(proxy) = proxy {
+        let proxy = HttpProxy::from_url(&proxy)?;
+        let before = Instant::now();
+        let stream = proxy
+            .connect(resolver, &format!("{}:{port}", authority.host()))
+            .await?;
+        // the tunnel stands in for the TCP connection
+        timings.tcp_connect = Some(before.elapsed());
+        info!("Tunneled through {}", proxy.addr());
+        stream
+    } else {
+        let before = Instant::now();
+        let addrs = resolver.resolve(authority.host(), port).await?;
+        timings.dns_lookup = Some(before.elapsed());
+        info!("Resolved {} addresses", addrs.len());
+
+        let before = Instant::now();
+        let connected = happy_eyeballs(&addrs, DEFAULT_ATTEMPT_DELAY).await?;
+        timings.tcp_connect = Some(before.elapsed());
+        for attempt in &connected.attempts {
+            info!(
+                "  {} started at {:?}, finished at {:?}: {:?}",
+                attempt.addr, attempt.started, attempt.finished, attempt.outcome
+            );
+        }
+        connected.stream.set_nodelay(true)?;
+        info!("Connected to {}", connected.addr);
+        connected.stream
+    };
 
     let before = Instant::now();
-    let mut stream = connector.connect("example.org".try_into()?, stream).await?;
-    info!("{:?} TLS handshake", before.elapsed());
+    let stream = connector
+        .connect(authority.host().try_into()?, stream)
+        .await?;
+    timings.tls_handshake = Some(before.elapsed());
+
+    let mut conn = http1::ClientConnection::new(stream);
+    conn.set_connect_timings(timings);
+    Ok(conn)
+}
 
-    let before = Instant::now();
-    let req = [
-        "GET / HTTP/1.1",
-        "host: example.org",
-        "user-agent: cool-bear/1.0",
-        "connection: close",
-        "",
-        "",
-    ]
-    .join("\r\n");
-    stream.write_all(req.as_bytes()).await?;
-    info!("{:?} Request send", before.elapsed());
-
-    let mut accum: Vec<u8> = Default::default();
-    let mut rd_buf = [0u8; 1024];
+/// Logs how long each phase of a request took, as the fields of a span
+fn log_timings(url: &http::Uri, timings: RequestTimings) {
+    let span = RequestTimings::span();
+    timings.record(&span);
+    span.in_scope(|| info!("{url} took {:?}", timings.total()));
+}
 
-    let before = Instant::now();
-    let (body_offset, res) = loop {
-        let n = stream.read(&mut rd_buf[..]).await?;
-        if n == 0 {
-            return Err(eyre!(
-                "unexpected EOF (server closed connection during headers)"
-            ));
-        }
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    setup()?;
 
-        accum.extend_from_slice(&rd_buf[..n]);
+    let connector = set_tls_connector()?;
+    // a redirect may send us back to a host we've already looked up
+    let resolver = CachingResolver::new(SystemResolver, Duration::from_secs(60));
+
+    // the connection is kept alive, so the second request doesn't need a new
+    // TCP connection or TLS handshake (unless a redirect sends us elsewhere).
+    let mut host = "example.org".to_owned();
+    let mut conn = connect(&connector, &resolver, &host).await?;
+    let policy = RedirectPolicy::default();
+    // sent as `authorization`, but only to the origin we started with
+    let authorization = std::env::var("AUTHORIZATION").ok();
+    for path in ["/", "/index.html"] {
+        let mut redirects = policy.start("GET", format!("https://{host}{path}").parse()?)?;
+        loop {
+            let url = redirects.url().clone();
+            if url.scheme_str() != Some("https") {
+                return Err(eyre!("Refusing to follow redirect to {url}"));
+            }
+            let authority = url.authority().map(|a| a.as_str()).unwrap_or_default();
+            if authority != host || !conn.is_reusable() {
+                info!("Connecting to {authority}");
+                host = authority.to_owned();
+                conn = connect(&connector, &resolver, &host).await?;
+            }
 
-        match http1::response(&accum) {
-            Err(e) => {
-                if e.is_incomplete() {
-                    info!("Need to read more, continuing");
-                    continue;
+            let target = url.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+            let mut req = http1::RequestHead::builder(redirects.method(), target)
+                .header("host", &host)
+                .header("user-agent", "cool-bear/1.0")
+                .header("accept-encoding", encoding::ACCEPT_ENCODING);
+            if let Some(authorization) = &authorization {
+                if redirects.keep_header("authorization") {
+                    req = req.header("authorization", authorization);
                 } else {
-                    return Err(eyre!("parse error: {e}"));
+                    info!("Not sending credentials to {authority}");
                 }
             }
-            Ok((remain, res)) => {
-                let body_offset = accum.offset(remain);
-                break (body_offset, res);
+            let req = req.build()?;
+            let (res, body) = conn.send(&req, &[]).await?;
+            info!(
+                "{:?} {} {}, body framing: {:?}",
+                res.version,
+                res.status,
+                String::from_utf8_lossy(res.status_text),
+                res.framing()?
+            );
+            if redirects.follow(res.status, res.location())? {
+                log_timings(&url, conn.timings());
+                info!("Redirected to {}", redirects.url());
+                // the body of the redirect is drained by the next `send`
+                continue;
             }
-        };
-    };
-    info!("{:?} Response header read", before.elapsed());
-
-    let before = Instant::now();
-    let mut body_accum = accum[body_offset..].to_vec();
-    // header names are case-insensitive, let's get it right. we're assuming
-    // that the absence of content-length means there's no body, and also we
-    // don't support chunked transfer encoding.
-    let content_length = res
-        .headers
-        .iter()
-        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
-        .map(|(_, v)| v.parse::<usize>().unwrap())
-        .unwrap_or_default();
-
-    while body_accum.len() < content_length {
-        let n = stream.read(&mut rd_buf[..]).await?;
-        if n == 0 {
-            return Err(eyre!("unexpected EOF (peer closed connection during body)"));
+            let codings = encoding::content_codings(res.headers.content_encoding())?;
+            info!("content codings: {codings:?}");
+
+            // the body is streamed (and decompressed on the fly), rather than
+            // accumulated in memory: this could just as well be a file, or a hasher.
+            let mut body = encoding::decode(body, &codings);
+            let body_len = tokio::io::copy(&mut body, &mut tokio::io::sink()).await?;
+            info!("Response body read ({body_len} bytes, decoded)");
+            drop(body);
+            log_timings(&url, conn.timings());
+            break;
         }
 
-        body_accum.extend_from_slice(&rd_buf[..n]);
+        if !conn.is_open() {
+            info!("Server closed the connection");
+            break;
+        }
     }
-    info!("{:?} Response body read", before.elapsed());
 
     Ok(())
 }
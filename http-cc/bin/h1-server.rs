@@ -0,0 +1,41 @@
+use std::str::FromStr;
+
+use httplib::http1::{RequestHead, Server, ServerResponse};
+use tracing::info;
+use tracing_subscriber::{filter::targets::Targets, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Serves a tiny page on `LISTEN_ADDR` (default `127.0.0.1:8080`), as a local
+/// stand-in for example.org.
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install().unwrap();
+
+    let filter_layer =
+        Targets::from_str(std::env::var("RUST_LOG").as_deref().unwrap_or("info")).unwrap();
+    let format_layer = tracing_subscriber::fmt::layer();
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(format_layer)
+        .init();
+
+    let addr = std::env::var("LISTEN_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".into());
+    let server = Server::bind(addr, |req: RequestHead, body: Vec<u8>| async move {
+        info!(
+            "{} {} ({} bytes of body)",
+            req.method(),
+            req.target(),
+            body.len()
+        );
+        let page = "<!doctype html><html><body><h1>Hello from httplib!</h1></body></html>\n";
+        ServerResponse::builder(200)
+            .header("content-type", "text/html; charset=utf-8")
+            .body(page)
+            .build()
+            .expect("static headers are valid")
+    })
+    .await?;
+
+    info!("Listening on {}", server.local_addr()?);
+    server.run().await;
+    Ok(())
+}
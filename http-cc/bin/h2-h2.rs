@@ -1,8 +1,10 @@
-use std::{net::ToSocketAddrs, str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc};
 
-use color_eyre::eyre::eyre;
+use httplib::{
+    dns::{self, SystemResolver},
+    proxy::Socks5Proxy,
+};
 use rustls::{Certificate, ClientConfig, KeyLogFile, RootCertStore};
-use tokio::net::TcpStream;
 use tokio_rustls::TlsConnector;
 use tracing::info;
 use tracing_subscriber::{filter::targets::Targets, layer::SubscriberExt, util::SubscriberInitExt};
@@ -43,13 +45,15 @@ async fn main() -> color_eyre::Result<()> {
 
     let connector = set_tls_connecter()?;
 
-    let addr = "example.org:443"
-        .to_socket_addrs()?
-        .next()
-        .ok_or_else(|| eyre!("Failed to resolve address for example.org:443"))?;
-
-    info!("Establishing TCP connection...");
-    let stream = TcpStream::connect(addr).await?;
+    // the proxy resolves the name, and the TLS session goes through it
+    let stream = if let Ok(proxy) = std::env::var("ALL_PROXY") {
+        let proxy = Socks5Proxy::from_url(&proxy)?;
+        info!("Connecting through SOCKS5 proxy {}...", proxy.addr());
+        proxy.connect(&SystemResolver, "example.org:443").await?
+    } else {
+        info!("Establishing TCP connection...");
+        dns::connect(&SystemResolver, "example.org", 443).await?
+    };
 
     info!("Establishing TLS session...");
     let stream = connector.connect("example.org".try_into()?, stream).await?;
@@ -1,14 +1,16 @@
-use std::{net::ToSocketAddrs, str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc};
 
-use bytes::BytesMut;
 use color_eyre::eyre::eyre;
-use httplib::http2::{self, DataFlags, Frame, FrameType, HeadersFlags, SettingsFlags};
-use nom::Offset;
-use rustls::{Certificate, ClientConfig, KeyLogFile, RootCertStore};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+use httplib::{
+    dns::{self, SystemResolver},
+    encoding,
+    http2::ClientConnection,
+    proxy::Socks5Proxy,
+    redirect::RedirectPolicy,
 };
+use rustls::{Certificate, ClientConfig, KeyLogFile, RootCertStore};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+use tokio_rustls::{client::TlsStream, TlsConnector};
 use tracing::info;
 use tracing_subscriber::{filter::targets::Targets, layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -46,122 +48,100 @@ async fn real_main() -> color_eyre::Result<()> {
 
     let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
 
-    info!("Performing DNS lookup");
-    let addr = "example.org:443"
-        .to_socket_addrs()?
-        .next()
-        .ok_or_else(|| eyre!("Failed to resolve address for example.org:443"))?;
+    // same interface as the h2 crate: we build an `http::Request`, and the
+    // connection turns it into pseudo-headers + headers. Redirects to the
+    // same host reuse the connection, on a new stream.
+    let mut host = "example.org".to_owned();
+    let mut conn = connect(&connector, &host).await?;
+    let mut redirects =
+        RedirectPolicy::default().start("GET", format!("https://{host}/").parse()?)?;
+    let res = loop {
+        let url = redirects.url().clone();
+        if url.scheme_str() != Some("https") {
+            return Err(eyre!("Refusing to follow redirect to {url}"));
+        }
+        let authority = url.authority().map(|a| a.as_str()).unwrap_or_default();
+        if authority != host {
+            info!("Connecting to {authority}");
+            host = authority.to_owned();
+            conn = connect(&connector, &host).await?;
+        }
 
-    info!("Establishing TCP connection...");
-    let stream = TcpStream::connect(addr).await?;
+        let req = http::Request::builder()
+            .method(redirects.method())
+            .uri(url)
+            .header("user-agent", "fasterthanlime/http-crash-course")
+            // http://www.gnuterrypratchett.com/
+            .header("x-clacks-overhead", "GNU Terry Pratchett")
+            .header("accept-encoding", encoding::ACCEPT_ENCODING)
+            .body(())?;
+        let res = conn.send(&req).await?;
+        info!("response status: {}", res.status());
+        for (name, value) in res.headers() {
+            info!("response header: {name}: {value:?}");
+        }
 
-    info!("Establishing TLS session...");
-    let mut stream = connector.connect("example.org".try_into()?, stream).await?;
+        let location = res
+            .headers()
+            .get(http::header::LOCATION)
+            .and_then(|l| l.to_str().ok());
+        if !redirects.follow(res.status().as_u16(), location)? {
+            break res;
+        }
+        info!("Redirected to {}", redirects.url());
+    };
+
+    let codings = encoding::content_codings(
+        res.headers()
+            .get_all(http::header::CONTENT_ENCODING)
+            .iter()
+            .map(|v| v.as_bytes()),
+    )?;
+    let mut decoded = Vec::new();
+    encoding::decode(&res.body()[..], &codings)
+        .read_to_end(&mut decoded)
+        .await?;
+    info!(
+        "response body ({} bytes, {} decoded, {codings:?}): {}",
+        res.body().len(),
+        decoded.len(),
+        String::from_utf8_lossy(&decoded[..std::cmp::min(100, decoded.len())])
+    );
 
-    info!("Establishing HTTP/2 connection...");
+    info!("All done!");
+    Ok(())
+}
 
-    info!("Writing preface");
-    stream.write_all(http2::PREFACE).await?;
-
-    let settings = Frame::new(FrameType::Settings(Default::default()), 0);
-    info!("> {settings:?}");
-    settings.write(&mut stream).await?;
-
-    let mut encoder = hpack::Encoder::new();
-    let headers: &[(&[u8], &[u8])] = &[
-        (b":method", b"GET"),
-        (b":path", b"/"),
-        (b":scheme", b"https"),
-        (b":authority", b"example.org"),
-        (b"user-agent", b"fasterthanlime/http-crash-course"),
-        // http://www.gnuterrypratchett.com/
-        (b"x-clacks-overhead", b"GNU Terry Pratchett"),
-    ];
-    let mut headers_frame = Frame::new(
-        FrameType::Headers(HeadersFlags::EndHeaders | HeadersFlags::EndStream),
-        1,
-    );
-    headers_frame.payload.0 = encoder.encode(headers.iter().copied());
-    info!("> {headers_frame:?}");
-    headers_frame.write(&mut stream).await?;
-
-    let mut decoder = hpack::Decoder::new();
-
-    let mut buf: BytesMut = Default::default();
-    loop {
-        info!("Reading frame ({} bytes so far)", buf.len());
-        if stream.read_buf(&mut buf).await? == 0 {
-            info!("connection closed!");
-            return Ok(());
-        }
+/// Connects to `host` (a URL authority, with an optional port) over TLS,
+/// through the SOCKS5 proxy in `ALL_PROXY` if it's set, and starts an
+/// HTTP/2 connection.
+async fn connect(
+    connector: &TlsConnector,
+    host: &str,
+) -> color_eyre::Result<ClientConnection<TlsStream<TcpStream>>> {
+    let authority: http::uri::Authority = host.parse()?;
+    let port = authority.port_u16().unwrap_or(443);
+
+    // the proxy resolves the name, and the TLS session goes through it
+    let stream = if let Ok(proxy) = std::env::var("ALL_PROXY") {
+        let proxy = Socks5Proxy::from_url(&proxy)?;
+        info!("Connecting through SOCKS5 proxy {}...", proxy.addr());
+        proxy
+            .connect(&SystemResolver, &format!("{}:{port}", authority.host()))
+            .await?
+    } else {
+        info!("Establishing TCP connection...");
+        dns::connect(&SystemResolver, authority.host(), port).await?
+    };
 
-        let slice = &buf[..];
-        let frame = match Frame::parse(slice) {
-            Ok((rest, frame)) => {
-                buf = buf.split_off(slice.offset(rest));
-                frame
-            }
-            Err(e) => {
-                if e.is_incomplete() {
-                    // keep reading!
-                    continue;
-                }
-                panic!("parse error: {e}");
-            }
-        };
-
-        info!("< {frame:?}");
-        match &frame.frame_type {
-            FrameType::Settings(flags) => {
-                if !flags.contains(SettingsFlags::Ack) {
-                    info!("Acknowledging server settings");
-                    let settings = Frame::new(FrameType::Settings(SettingsFlags::Ack.into()), 0);
-                    info!("> {settings:?}");
-                    settings.write(&mut stream).await?;
-                }
-            }
-            FrameType::Headers(flags) => {
-                assert!(
-                    !flags.contains(HeadersFlags::Padded),
-                    "padding not supported"
-                );
-                assert!(
-                    !flags.contains(HeadersFlags::Priority),
-                    "priority not supported"
-                );
-                assert!(
-                    flags.contains(HeadersFlags::EndHeaders),
-                    "continuation frames not supported"
-                );
-
-                let headers = decoder.decode(&frame.payload.0).unwrap();
-                for (name, value) in headers {
-                    info!(
-                        "response header: {}: {}",
-                        String::from_utf8_lossy(&name),
-                        String::from_utf8_lossy(&value)
-                    );
-                }
-            }
-            FrameType::Data(flags) => {
-                assert!(!flags.contains(DataFlags::Padded), "padding not supported");
-                assert!(
-                    flags.contains(DataFlags::EndStream),
-                    "streaming response bodies not supported"
-                );
-
-                let response_body = String::from_utf8_lossy(&frame.payload.0);
-                info!(
-                    "response body: {}",
-                    &response_body[..std::cmp::min(100, response_body.len())]
-                );
-
-                info!("All done!");
-                return Ok(());
-            }
-            _ => {
-                // ignore other types of frames
-            }
-        }
+    info!("Establishing TLS session...");
+    let stream = connector
+        .connect(authority.host().try_into()?, stream)
+        .await?;
+    if stream.get_ref().1.alpn_protocol() != Some(b"h2") {
+        return Err(eyre!("{host} doesn't speak HTTP/2"));
     }
+
+    info!("Establishing HTTP/2 connection...");
+    ClientConnection::handshake(stream).await
 }
@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use color_eyre::eyre::eyre;
+use httplib::{
+    connect::{happy_eyeballs, DEFAULT_ATTEMPT_DELAY},
+    dns::{Resolver, SystemResolver},
+    http2::ClientConnection,
+    timings::RequestTimings,
+};
+use tokio::time::Instant;
+use tracing::info;
+use tracing_subscriber::{filter::targets::Targets, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Talks cleartext HTTP/2 to `H2C_ADDR` (default `localhost:8080`), with prior
+/// knowledge, or through an HTTP/1.1 upgrade if `H2C_UPGRADE` is set.
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install().unwrap();
+
+    let filter_layer =
+        Targets::from_str(std::env::var("RUST_LOG").as_deref().unwrap_or("info")).unwrap();
+    let format_layer = tracing_subscriber::fmt::layer();
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(format_layer)
+        .init();
+
+    let host = std::env::var("H2C_ADDR").unwrap_or_else(|_| "localhost:8080".into());
+    let upgrade = std::env::var_os("H2C_UPGRADE").is_some();
+
+    let authority: http::uri::Authority = host.parse()?;
+    let port = authority
+        .port_u16()
+        .ok_or_else(|| eyre!("H2C_ADDR must have a port"))?;
+
+    let mut timings = RequestTimings::default();
+    let before = Instant::now();
+    let addrs = SystemResolver.resolve(authority.host(), port).await?;
+    timings.dns_lookup = Some(before.elapsed());
+    let before = Instant::now();
+    let stream = happy_eyeballs(&addrs, DEFAULT_ATTEMPT_DELAY).await?.stream;
+    timings.tcp_connect = Some(before.elapsed());
+
+    let mut conn = if upgrade {
+        info!("Upgrading from HTTP/1.1...");
+        let (conn, res) = ClientConnection::upgrade(stream, &host, "/").await?;
+        info!("Got response to the upgrade request: {:?}", res.status());
+        info!("{}", String::from_utf8_lossy(res.body()));
+        // the connection was set up for the upgrade request
+        log_timings(RequestTimings {
+            dns_lookup: timings.dns_lookup,
+            tcp_connect: timings.tcp_connect,
+            ..conn.timings()
+        });
+        conn
+    } else {
+        info!("Sending preface (prior knowledge)...");
+        let mut conn = ClientConnection::handshake(stream).await?;
+        conn.set_connect_timings(timings);
+        conn
+    };
+
+    let req = http::Request::get(format!("http://{host}/")).body(())?;
+    let res = conn.send(&req).await?;
+    info!("Got response: {:?}", res.status());
+    for (name, value) in res.headers() {
+        info!("{name}: {value:?}");
+    }
+    info!("{}", String::from_utf8_lossy(res.body()));
+    log_timings(conn.timings());
+
+    Ok(())
+}
+
+/// Logs how long each phase of a request took, as the fields of a span
+fn log_timings(timings: RequestTimings) {
+    let span = RequestTimings::span();
+    timings.record(&span);
+    span.in_scope(|| info!("Request took {:?}", timings.total()));
+}
@@ -0,0 +1,151 @@
+use std::{fmt, io, net::SocketAddr, time::Duration};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::{net::TcpStream, time::Instant};
+
+/// How long to wait for a connection attempt before starting the next one in
+/// parallel, as recommended by https://www.rfc-editor.org/rfc/rfc8305#section-8
+pub const DEFAULT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// A connection attempt made by [happy_eyeballs]. Times are relative to the
+/// start of the whole race.
+#[derive(Debug)]
+pub struct Attempt {
+    pub addr: SocketAddr,
+    pub started: Duration,
+    /// When the attempt succeeded or failed, `None` if it was canceled
+    pub finished: Option<Duration>,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Connected,
+    Failed(io::Error),
+    /// Another attempt won the race first
+    Canceled,
+}
+
+/// The winner of [happy_eyeballs], and how the race went
+#[derive(Debug)]
+pub struct Connected {
+    pub stream: TcpStream,
+    pub addr: SocketAddr,
+    /// Every attempt made, in the order they were started
+    pub attempts: Vec<Attempt>,
+}
+
+/// Returned by [happy_eyeballs] when every attempt failed (or there was
+/// nothing to try)
+#[derive(Debug)]
+pub struct ConnectError {
+    pub attempts: Vec<Attempt>,
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.attempts.last() {
+            None => write!(f, "no addresses to connect to"),
+            Some(last) => {
+                write!(
+                    f,
+                    "connection failed after {} attempts",
+                    self.attempts.len()
+                )?;
+                if let Outcome::Failed(e) = &last.outcome {
+                    write!(f, " (last one to {}: {e})", last.addr)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+impl From<ConnectError> for io::Error {
+    /// Keeps the kind of the last failure, so that callers can still tell a
+    /// refused connection from a timeout.
+    fn from(e: ConnectError) -> Self {
+        let kind = match e.attempts.last().map(|a| &a.outcome) {
+            Some(Outcome::Failed(last)) => last.kind(),
+            _ => io::ErrorKind::NotFound,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Orders addresses so that IPv6 and IPv4 alternate, starting with the
+/// family of the first one (resolvers put the preferred family first).
+/// Within a family, the original order is kept.
+/// See https://www.rfc-editor.org/rfc/rfc8305#section-4
+pub fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return vec![];
+    };
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) =
+        addrs.iter().partition(|a| a.is_ipv6() == first.is_ipv6());
+    preferred.reverse();
+    other.reverse();
+
+    let mut out = Vec::with_capacity(addrs.len());
+    while let Some(addr) = preferred.pop() {
+        out.push(addr);
+        if let Some(addr) = other.pop() {
+            out.push(addr);
+        }
+    }
+    out.extend(other.into_iter().rev());
+    out
+}
+
+/// Connects to one of `addrs`, trying them in [interleave]d order. Attempts
+/// overlap: the next one starts when the previous fails, or after
+/// `attempt_delay` if it's still pending, so that an unreachable address
+/// (like IPv6 on a network that silently drops it) only costs that delay.
+/// The first connection established wins, and the others are dropped.
+/// See https://www.rfc-editor.org/rfc/rfc8305#section-5
+pub async fn happy_eyeballs(
+    addrs: &[SocketAddr],
+    attempt_delay: Duration,
+) -> Result<Connected, ConnectError> {
+    let start = Instant::now();
+    let mut candidates = interleave(addrs).into_iter().peekable();
+    let mut attempts: Vec<Attempt> = Vec::new();
+    let mut pending = FuturesUnordered::new();
+
+    // each turn starts an attempt: the first one, or the next one after a
+    // failure or a delay
+    loop {
+        if let Some(addr) = candidates.next() {
+            let index = attempts.len();
+            attempts.push(Attempt {
+                addr,
+                started: start.elapsed(),
+                finished: None,
+                outcome: Outcome::Canceled,
+            });
+            pending.push(async move { (index, TcpStream::connect(addr).await) });
+        }
+        if pending.is_empty() {
+            return Err(ConnectError { attempts });
+        }
+
+        let has_more = candidates.peek().is_some();
+        tokio::select! {
+            Some((index, res)) = pending.next() => {
+                let attempt = &mut attempts[index];
+                attempt.finished = Some(start.elapsed());
+                match res {
+                    Ok(stream) => {
+                        attempt.outcome = Outcome::Connected;
+                        let addr = attempt.addr;
+                        return Ok(Connected { stream, addr, attempts });
+                    }
+                    Err(e) => attempt.outcome = Outcome::Failed(e),
+                }
+            }
+            _ = tokio::time::sleep(attempt_delay), if has_more => {}
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{net::TcpStream, time::Instant};
+
+use crate::connect::{happy_eyeballs, DEFAULT_ATTEMPT_DELAY};
+
+/// What [Resolver::resolve] returns
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Turns a host name into socket addresses, without blocking the runtime.
+///
+/// IP literals like `127.0.0.1` or `::1` resolve to themselves.
+pub trait Resolver: Send + Sync {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a>;
+}
+
+impl<R> Resolver for Arc<R>
+where
+    R: Resolver + ?Sized,
+{
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        (**self).resolve(host, port)
+    }
+}
+
+/// Uses the system resolver (`getaddrinfo`, which honors `/etc/hosts`), on
+/// tokio's blocking thread pool.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        let host = host.to_owned();
+        Box::pin(async move {
+            if let Some(ip) = parse_ip(&host) {
+                return Ok(vec![SocketAddr::new(ip, port)]);
+            }
+            let addrs =
+                tokio::task::spawn_blocking(move || (host.as_str(), port).to_socket_addrs())
+                    .await??;
+            Ok(addrs.collect())
+        })
+    }
+}
+
+/// Resolves names from a fixed table, like a hosts file, so that tests (and
+/// local setups) don't depend on DNS. Names that aren't in the table go to
+/// the fallback resolver if there's one, and fail otherwise.
+#[derive(Default)]
+pub struct StaticResolver {
+    /// Keyed by lowercase host name
+    hosts: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Box<dyn Resolver>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Maps `host` to `ip`, in addition to any address it already had
+    pub fn insert(mut self, host: &str, ip: IpAddr) -> Self {
+        self.hosts
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .push(ip);
+        self
+    }
+
+    /// Parses lines in the hosts file format, like `127.0.0.1 example.org
+    /// www.example.org`. Comments start with `#`, and lines whose address
+    /// doesn't parse are skipped, like the system resolver does.
+    /// See https://man7.org/linux/man-pages/man5/hosts.5.html
+    pub fn from_hosts(contents: &str) -> Self {
+        let mut resolver = Self::new();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(Ok(ip)) = fields.next().map(str::parse::<IpAddr>) else {
+                continue;
+            };
+            for host in fields {
+                resolver = resolver.insert(host, ip);
+            }
+        }
+        resolver
+    }
+
+    /// Resolves names that aren't in the table with another resolver
+    pub fn fallback(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.fallback = Some(Box::new(resolver));
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            if let Some(ip) = parse_ip(host) {
+                return Ok(vec![SocketAddr::new(ip, port)]);
+            }
+            if let Some(ips) = self.hosts.get(&host.to_ascii_lowercase()) {
+                return Ok(ips.iter().map(|&ip| SocketAddr::new(ip, port)).collect());
+            }
+            match &self.fallback {
+                Some(fallback) => fallback.resolve(host, port).await,
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no static entry for {host:?}"),
+                )),
+            }
+        })
+    }
+}
+
+/// Remembers the answers of another resolver for a while. The system
+/// resolver doesn't tell how long answers are valid, so every entry gets
+/// the same TTL. Failures aren't cached.
+pub struct CachingResolver<R> {
+    inner: R,
+    ttl: Duration,
+    /// Keyed by lowercase host name and port
+    cache: Mutex<HashMap<(String, u16), CacheEntry>>,
+}
+
+struct CacheEntry {
+    expires: Instant,
+    addrs: Vec<SocketAddr>,
+}
+
+impl<R> CachingResolver<R>
+where
+    R: Resolver,
+{
+    pub fn new(inner: R, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cache: Default::default(),
+        }
+    }
+
+    /// Forgets every answer, for example after a network change
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl<R> Resolver for CachingResolver<R>
+where
+    R: Resolver,
+{
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let key = (host.to_ascii_lowercase(), port);
+            if let Some(entry) = self.cache.lock().unwrap().get(&key) {
+                if Instant::now() < entry.expires {
+                    return Ok(entry.addrs.clone());
+                }
+            }
+
+            let addrs = self.inner.resolve(host, port).await?;
+            let mut cache = self.cache.lock().unwrap();
+            // drop whatever expired while we're at it, so that the cache
+            // doesn't grow forever
+            let now = Instant::now();
+            cache.retain(|_, entry| now < entry.expires);
+            let entry = CacheEntry {
+                expires: now + self.ttl,
+                addrs: addrs.clone(),
+            };
+            cache.insert(key, entry);
+            Ok(addrs)
+        })
+    }
+}
+
+/// Resolves `host` and connects to one of its addresses, see
+/// [crate::connect::happy_eyeballs]
+pub async fn connect(resolver: &dyn Resolver, host: &str, port: u16) -> io::Result<TcpStream> {
+    let addrs = resolver.resolve(host, port).await?;
+    let connected = happy_eyeballs(&addrs, DEFAULT_ATTEMPT_DELAY).await?;
+    Ok(connected.stream)
+}
+
+/// Parses an IP literal, bracketed or not for IPv6
+fn parse_ip(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
@@ -0,0 +1,93 @@
+use std::{fmt, pin::Pin};
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use tokio::io::{AsyncRead, BufReader};
+
+use crate::headers::trim_ows;
+
+/// A value for the `accept-encoding` request header, listing every coding
+/// [decode] supports.
+pub const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/// See https://httpwg.org/specs/rfc9110.html#content.codings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCoding {
+    Gzip,
+    /// Despite the name, this is the zlib format, not raw deflate
+    Deflate,
+    Brotli,
+    Identity,
+}
+
+impl ContentCoding {
+    /// Parses a coding name, ignoring case. `x-gzip` is accepted as an alias
+    /// of `gzip`, as required by the spec.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        let name = trim_ows(name);
+        [
+            (&b"gzip"[..], Self::Gzip),
+            (b"x-gzip", Self::Gzip),
+            (b"deflate", Self::Deflate),
+            (b"br", Self::Brotli),
+            (b"identity", Self::Identity),
+        ]
+        .into_iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, coding)| coding)
+    }
+}
+
+/// Returned when a body was encoded with something [decode] doesn't support
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedCoding(pub String);
+
+impl fmt::Display for UnsupportedCoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported content-encoding {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedCoding {}
+
+/// Parses the values of the `content-encoding` header (there may be several
+/// lines of it), and returns the codings in the order they were applied.
+pub fn content_codings<'a>(
+    values: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<Vec<ContentCoding>, UnsupportedCoding> {
+    values
+        .into_iter()
+        .flat_map(|v| v.split(|&c| c == b','))
+        .map(trim_ows)
+        .filter(|v| !v.is_empty())
+        .map(|name| {
+            ContentCoding::from_name(name)
+                .ok_or_else(|| UnsupportedCoding(String::from_utf8_lossy(name).into_owned()))
+        })
+        .collect()
+}
+
+/// A body being decoded, see [decode]
+pub type DecodedBody<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+/// Wraps `body` in streaming decoders, so that reading from it returns the
+/// decoded bytes. `codings` are in the order they were applied, like the
+/// result of [content_codings], so they're undone from last to first.
+///
+/// `body` can be a [crate::http1::BodyReader], a [crate::http1::Body], or the
+/// concatenated payloads of HTTP/2 DATA frames. Servers only compress if the
+/// request had an `accept-encoding` header, like [ACCEPT_ENCODING].
+pub fn decode<'a, R>(body: R, codings: &[ContentCoding]) -> DecodedBody<'a>
+where
+    R: AsyncRead + Send + 'a,
+{
+    let mut body: DecodedBody<'a> = Box::pin(body);
+    for coding in codings.iter().rev() {
+        body = match coding {
+            ContentCoding::Gzip => Box::pin(GzipDecoder::new(BufReader::new(body))),
+            ContentCoding::Deflate => Box::pin(ZlibDecoder::new(BufReader::new(body))),
+            ContentCoding::Brotli => Box::pin(BrotliDecoder::new(BufReader::new(body))),
+            ContentCoding::Identity => body,
+        };
+    }
+    body
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+
+use nom::{
+    error::{ErrorKind, FromExternalError},
+    ErrorConvert, IResult, Offset, Parser,
+};
+
+/// An error returned by the [crate::http1] and [crate::http2] parsers, when
+/// the input is invalid (as opposed to incomplete).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Where the problem was found, in bytes from the start of the input
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Something's wrong with a line like `HTTP/1.1 200 OK`
+    InvalidStatusLine,
+    /// Something's wrong with a line like `GET / HTTP/1.1`
+    InvalidRequestLine,
+    /// A header name is empty, or contains characters that aren't allowed in
+    /// a token
+    InvalidHeaderName,
+    InvalidHeaderValue,
+    /// A header line is longer than [crate::http1::ParserLimits::max_header_line_len]
+    HeaderTooLarge,
+    /// There are more than [crate::http1::ParserLimits::max_headers] headers
+    TooManyHeaders,
+    /// The head is longer than [crate::http1::ParserLimits::max_head_len]
+    HeadTooLarge,
+    /// The reason phrase is longer than
+    /// [crate::http1::ParserLimits::max_status_text_len]
+    StatusTextTooLong,
+    /// Something's wrong with the framing of a chunked body
+    InvalidChunk,
+    /// An HTTP/2 frame (or its payload) is malformed
+    InvalidFrame,
+    /// The frame type isn't one from https://httpwg.org/specs/rfc9113.html#FrameTypes,
+    /// when parsing strictly, see [crate::http2::Frame::parse_strict]
+    UnknownFrameType(u8),
+    /// The frame is larger than the maximum frame size we advertised (HTTP/2),
+    /// or are willing to buffer (WebSocket)
+    FrameTooLarge {
+        len: u32,
+        max: u32,
+    },
+    /// A WebSocket frame is malformed, see
+    /// https://www.rfc-editor.org/rfc/rfc6455#section-5.2
+    InvalidWebSocketFrame,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at byte {})", self.kind, self.offset)
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidStatusLine => write!(f, "invalid status line"),
+            Self::InvalidRequestLine => write!(f, "invalid request line"),
+            Self::InvalidHeaderName => write!(f, "invalid header name"),
+            Self::InvalidHeaderValue => write!(f, "invalid header value"),
+            Self::HeaderTooLarge => write!(f, "header too large"),
+            Self::TooManyHeaders => write!(f, "too many headers"),
+            Self::HeadTooLarge => write!(f, "head too large"),
+            Self::StatusTextTooLong => write!(f, "status text too long"),
+            Self::InvalidChunk => write!(f, "invalid chunked body"),
+            Self::InvalidFrame => write!(f, "invalid frame"),
+            Self::UnknownFrameType(ty) => write!(f, "unknown frame type {ty:#x}"),
+            Self::FrameTooLarge { len, max } => {
+                write!(f, "frame too large ({len} bytes, max is {max})")
+            }
+            Self::InvalidWebSocketFrame => write!(f, "invalid websocket frame"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Returned when converting between our types and the ones from the [http]
+/// crate fails.
+#[derive(Debug)]
+pub enum ConvertError {
+    /// HTTP/1 types can't represent HTTP/2 messages, and vice versa
+    UnsupportedVersion(http::Version),
+    /// A pseudo-header like `:status` or `:path` is missing or invalid
+    InvalidPseudoHeader(&'static str),
+    /// A request with a relative URI and no `host` header can't be turned into
+    /// an HTTP/2 request, which needs an `:authority`
+    MissingAuthority,
+    Http(http::Error),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {version:?}"),
+            Self::InvalidPseudoHeader(name) => write!(f, "missing or invalid {name}"),
+            Self::MissingAuthority => write!(f, "missing authority"),
+            Self::Http(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> From<E> for ConvertError
+where
+    http::Error: From<E>,
+{
+    fn from(e: E) -> Self {
+        Self::Http(e.into())
+    }
+}
+
+/// The error type used by parsers internally: it remembers which part of the
+/// input it failed on, so that [finish] can turn that into an offset.
+#[derive(Debug)]
+pub(crate) struct RawError<'a> {
+    input: &'a [u8],
+    kind: Option<ParseErrorKind>,
+}
+
+pub(crate) type RawResult<'a, T> = IResult<&'a [u8], T, RawError<'a>>;
+
+impl<'a> RawError<'a> {
+    pub(crate) fn new(input: &'a [u8], kind: ParseErrorKind) -> Self {
+        Self {
+            input,
+            kind: Some(kind),
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for RawError<'a> {
+    fn from_error_kind(input: &'a [u8], _kind: ErrorKind) -> Self {
+        Self { input, kind: None }
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a, E> FromExternalError<&'a [u8], E> for RawError<'a> {
+    fn from_external_error(input: &'a [u8], _kind: ErrorKind, _e: E) -> Self {
+        Self { input, kind: None }
+    }
+}
+
+/// Lets [RawError] be used with [nom::bits::bits]
+impl<'a> ErrorConvert<RawError<'a>> for nom::error::Error<(&'a [u8], usize)> {
+    fn convert(self) -> RawError<'a> {
+        let (input, offset) = self.input;
+        RawError {
+            input: &input[offset / 8..],
+            kind: None,
+        }
+    }
+}
+
+/// Makes errors coming out of `f` have the given kind, unless a more
+/// specific one was already set by a nested parser.
+pub(crate) fn with_kind<'a, O, F>(
+    kind: ParseErrorKind,
+    mut f: F,
+) -> impl FnMut(&'a [u8]) -> RawResult<'a, O>
+where
+    F: Parser<&'a [u8], O, RawError<'a>>,
+{
+    move |i| {
+        f.parse(i).map_err(|e| {
+            e.map(|mut e| {
+                e.kind.get_or_insert(kind);
+                e
+            })
+        })
+    }
+}
+
+/// Converts the result of an internal parser to the public error type,
+/// computing the error offset relative to `start`.
+pub(crate) fn finish<'a, T>(
+    start: &'a [u8],
+    res: RawResult<'a, T>,
+    default: ParseErrorKind,
+) -> IResult<&'a [u8], T, ParseError> {
+    res.map_err(|e| {
+        e.map(|e| ParseError {
+            kind: e.kind.unwrap_or(default),
+            offset: start.offset(e.input),
+        })
+    })
+}
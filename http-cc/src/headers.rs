@@ -0,0 +1,220 @@
+/// A single header field, as it appeared on the wire.
+///
+/// Names are validated tokens, so they're always ASCII. Values are kept as
+/// raw bytes: they're usually ASCII too, but some servers send latin-1 (or
+/// worse), and that shouldn't make the whole response unreadable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    /// Returns true if this header has the given name, ignoring case
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// Returns the value as a string, or `None` if it isn't valid UTF-8
+    pub fn value_str(&self) -> Option<&'a str> {
+        std::str::from_utf8(self.value).ok()
+    }
+}
+
+/// A list of header fields that keeps them in the order they were received,
+/// duplicates included. Lookups are case-insensitive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap<'a> {
+    headers: Vec<Header<'a>>,
+}
+
+impl<'a> HeaderMap<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push(&mut self, header: Header<'a>) {
+        self.headers.push(header)
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Header<'a>> {
+        self.headers.iter()
+    }
+
+    /// Returns true if there's at least one header with the given name
+    pub fn contains(&self, name: &str) -> bool {
+        self.headers.iter().any(|h| h.is(name))
+    }
+
+    /// Returns the value of the first header with the given name, or `None`
+    /// if it's missing or not valid UTF-8.
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.get_bytes(name)
+            .and_then(|v| std::str::from_utf8(v).ok())
+    }
+
+    /// Returns the raw value of the first header with the given name
+    pub fn get_bytes(&self, name: &str) -> Option<&'a [u8]> {
+        self.get_all(name).next()
+    }
+
+    /// Returns the raw values of all headers with the given name, in order
+    pub fn get_all<'m>(&'m self, name: &'m str) -> impl Iterator<Item = &'a [u8]> + 'm {
+        self.headers
+            .iter()
+            .filter(move |h| h.is(name))
+            .map(|h| h.value)
+    }
+
+    /// Returns the elements of a comma-separated list header, like
+    /// `connection: keep-alive, upgrade`, across all lines with that name.
+    /// See https://httpwg.org/specs/rfc9110.html#abnf.extension
+    pub fn get_list<'m>(&'m self, name: &'m str) -> impl Iterator<Item = &'a [u8]> + 'm {
+        self.get_all(name)
+            .flat_map(|v| v.split(|&c| c == b','))
+            .map(trim_ows)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// Parses the `content-length` header. Repeated values are fine as long
+    /// as they're identical, anything else is an error, as required by
+    /// https://httpwg.org/specs/rfc9112.html#body.content-length
+    pub fn content_length(&self) -> Result<Option<u64>, HeaderError> {
+        let mut content_length = None;
+        for value in self.get_list("content-length") {
+            if !value.iter().all(u8::is_ascii_digit) {
+                return Err(HeaderError::InvalidContentLength);
+            }
+            // all digits, so it's ASCII: this can only fail on overflow
+            let value: u64 = std::str::from_utf8(value)
+                .unwrap()
+                .parse()
+                .map_err(|_| HeaderError::InvalidContentLength)?;
+
+            match content_length {
+                Some(prev) if prev != value => return Err(HeaderError::ConflictingContentLength),
+                _ => content_length = Some(value),
+            }
+        }
+
+        // `get_list` skips empty values, but a header with only empty values
+        // is still invalid
+        if content_length.is_none() && self.contains("content-length") {
+            return Err(HeaderError::InvalidContentLength);
+        }
+        Ok(content_length)
+    }
+
+    /// Returns the transfer codings applied to the body, in the order they
+    /// were applied, like `[gzip, chunked]`.
+    pub fn transfer_encoding(&self) -> Vec<&'a [u8]> {
+        self.get_list("transfer-encoding").collect()
+    }
+
+    /// Returns true if chunked is the last transfer coding applied, which is
+    /// the only way the body can be framed with [crate::http1::ChunkedDecoder]
+    pub fn is_chunked(&self) -> bool {
+        self.transfer_encoding()
+            .last()
+            .map(|coding| coding.eq_ignore_ascii_case(b"chunked"))
+            .unwrap_or_default()
+    }
+
+    /// Returns the content codings applied to the body, in the order they were
+    /// applied, see [crate::encoding::content_codings]
+    pub fn content_encoding(&self) -> Vec<&'a [u8]> {
+        self.get_list("content-encoding").collect()
+    }
+
+    /// Returns the connection options, like `[close]` or `[keep-alive]`
+    pub fn connection(&self) -> Vec<&'a [u8]> {
+        self.get_list("connection").collect()
+    }
+
+    /// Returns true if the `connection` header has the given option, ignoring
+    /// case.
+    pub fn has_connection_option(&self, option: &str) -> bool {
+        self.get_list("connection")
+            .any(|v| v.eq_ignore_ascii_case(option.as_bytes()))
+    }
+}
+
+impl<'a> FromIterator<Header<'a>> for HeaderMap<'a> {
+    fn from_iter<T: IntoIterator<Item = Header<'a>>>(iter: T) -> Self {
+        Self {
+            headers: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a, 'm> IntoIterator for &'m HeaderMap<'a> {
+    type Item = &'m Header<'a>;
+    type IntoIter = std::slice::Iter<'m, Header<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.headers.iter()
+    }
+}
+
+/// Returned by [HeaderMap] getters when a header's value makes no sense
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The `content-length` isn't a valid number
+    InvalidContentLength,
+    /// There are several `content-length` values, and they're not all the
+    /// same: there's no telling where the body ends.
+    ConflictingContentLength,
+    /// The `transfer-encoding` doesn't end with `chunked` (or the message is
+    /// HTTP/1.0), in a request, where the body can't be close-delimited.
+    InvalidTransferEncoding,
+    /// A request has both `transfer-encoding` and `content-length`. That's
+    /// how request smuggling works: a proxy and the server behind it may
+    /// disagree on where the body ends.
+    AmbiguousFraming,
+}
+
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidContentLength => write!(f, "invalid content-length"),
+            Self::ConflictingContentLength => write!(f, "conflicting content-length values"),
+            Self::InvalidTransferEncoding => write!(f, "invalid transfer-encoding"),
+            Self::AmbiguousFraming => write!(f, "both transfer-encoding and content-length"),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+/// Trims optional whitespace (spaces and tabs) from both ends, see
+/// https://httpwg.org/specs/rfc9110.html#whitespace
+pub(crate) fn trim_ows(mut s: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = s {
+        s = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = s {
+        s = rest;
+    }
+    s
+}
+
+/// Returns true for characters allowed in a token (header names, chunk
+/// extension names, etc.), see https://httpwg.org/specs/rfc9110.html#tokens
+pub(crate) fn is_tchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+/// Returns true for characters allowed in a header value: visible ASCII,
+/// spaces, tabs, and "obs-text" (anything >= 0x80), see
+/// https://httpwg.org/specs/rfc9110.html#fields.values
+pub(crate) fn is_field_vchar(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c.is_ascii_graphic() || c >= 0x80
+}
@@ -1,70 +1,361 @@
 use nom::{
-    bytes::streaming::{tag, take_until, take_while1},
+    branch::alt,
+    bytes::streaming::{tag, take_while1, take_while_m_n},
     character::is_digit,
-    combinator::{map_res, opt},
-    sequence::{preceded, terminated},
-    IResult,
+    combinator::{map_res, peek, value},
+    sequence::terminated,
+    IResult, Offset,
 };
 
+use crate::{
+    error::{with_kind, ParseError, ParseErrorKind, RawError, RawResult},
+    headers::{is_field_vchar, is_tchar, trim_ows, Header, HeaderError, HeaderMap},
+};
+
+mod body;
+pub use body::BodyReader;
+
+mod client;
+pub use client::{Body, ClientConnection};
+
+mod convert;
+
+mod chunked;
+pub use chunked::{chunk_size, Chunk, ChunkedDecoder};
+
+mod parser;
+pub use parser::{RequestParser, ResponseParser};
+
+mod pipeline;
+pub use pipeline::{Pipeline, PipelineError};
+
+mod request_head;
+pub use request_head::{InvalidHead, RequestHead, RequestHeadBuilder};
+
+mod server;
+pub use server::{
+    Handler, HandlerFuture, InvalidResponse, Server, ServerResponse, ServerResponseBuilder,
+    DEFAULT_MAX_BODY_LEN,
+};
+
+/// See https://httpwg.org/specs/rfc9112.html#http.version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+#[derive(Debug)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    /// Usually a path like `/index.html`, but can be a full URL when talking
+    /// to a proxy, see https://httpwg.org/specs/rfc9112.html#request.target
+    pub target: &'a str,
+    pub version: Version,
+    pub headers: HeaderMap<'a>,
+}
+
 #[derive(Debug)]
 pub struct Response<'a> {
+    pub version: Version,
     pub status: u16,
-    pub status_text: &'a str,
-    // header names/values could be non-UTF-8, but let's not care for this sample.
-    // we are however careful not to use a HashMap, since headers can repeat.
-    pub headers: Vec<(&'a str, &'a str)>,
+    /// The reason phrase, like `Not Found`. Like header values, it's kept
+    /// as raw bytes, since it may contain obs-text (latin-1, usually), see
+    /// [Response::status_text_str]
+    pub status_text: &'a [u8],
+    // we are careful not to use a HashMap, since headers can repeat.
+    pub headers: HeaderMap<'a>,
 }
 
 const CRLF: &str = "\r\n";
 
-// Looks like `HTTP/1.1 200 OK\r\n` or `HTTP/1.1 404 Not Found\r\n`
-pub fn response(i: &[u8]) -> IResult<&[u8], Response<'_>> {
-    let (i, _) = tag("HTTP/1.1 ")(i)?;
+/// How the end of a message body is found, see
+/// https://httpwg.org/specs/rfc9112.html#message.body.length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// There is no body at all
+    None,
+    /// The body is exactly this many bytes long
+    ContentLength(u64),
+    /// The body uses chunked transfer encoding, see [ChunkedDecoder]
+    Chunked,
+    /// The body goes on until the server closes the connection
+    CloseDelimited,
+}
+
+/// Limits enforced while parsing a message head, so that a hostile (or
+/// broken) peer can't make us buffer forever. Exceeding any of them fails
+/// the parse with a dedicated [ParseErrorKind].
+#[derive(Debug, Clone, Copy)]
+pub struct ParserLimits {
+    /// Maximum number of header lines, see [ParseErrorKind::TooManyHeaders]
+    pub max_headers: usize,
+    /// Maximum length of a single header line, not counting the CRLF, see
+    /// [ParseErrorKind::HeaderTooLarge]
+    pub max_header_line_len: usize,
+    /// Maximum length of the whole head: start line, headers and the empty
+    /// line that ends them, see [ParseErrorKind::HeadTooLarge]
+    pub max_head_len: usize,
+    /// Maximum length of a reason phrase like `Not Found`, see
+    /// [ParseErrorKind::StatusTextTooLong]
+    pub max_status_text_len: usize,
+}
+
+impl Default for ParserLimits {
+    /// Roughly what nginx and Apache accept out of the box: 100 headers of
+    /// up to 8KiB each, and 32KiB for the whole head.
+    fn default() -> Self {
+        Self {
+            max_headers: 100,
+            max_header_line_len: 8 * 1024,
+            max_head_len: 32 * 1024,
+            max_status_text_len: 1024,
+        }
+    }
+}
+
+// Looks like `HTTP/1.1 200 OK\r\n` or `HTTP/1.1 404 Not Found\r\n`. Some
+// servers send `HTTP/1.0 200 OK\r\n`, or `HTTP/1.1 200\r\n` (no reason phrase)
+pub fn response(i: &[u8]) -> IResult<&[u8], Response<'_>, ParseError> {
+    response_with_limits(i, &Default::default())
+}
+
+/// Like [response], with custom limits
+pub fn response_with_limits<'a>(
+    i: &'a [u8],
+    limits: &ParserLimits,
+) -> IResult<&'a [u8], Response<'a>, ParseError> {
+    ResponseParser::with_limits(*limits).parse(i)
+}
 
-    let (i, status) = terminated(u16_text, ws)(i)?;
-    let (i, status_text) =
-        map_res(terminated(take_until(CRLF), tag(CRLF)), std::str::from_utf8)(i)?;
+/// Parses a status line, returning the version, status code and reason phrase
+fn status_line<'a>(i: &'a [u8], limits: &ParserLimits) -> RawResult<'a, (Version, u16, &'a [u8])> {
+    with_kind(ParseErrorKind::InvalidStatusLine, |i| {
+        let (i, version) = terminated(version, ws)(i)?;
+
+        let (i, status) = status_code(i)?;
+        // the reason phrase is optional, and so is the space before it if
+        // it's missing (even though the RFC says it should be there)
+        let (i, _) = alt((ws, value((), peek(tag(CRLF)))))(i)?;
+        let (i, status_text) = line(
+            limits.max_status_text_len,
+            ParseErrorKind::StatusTextTooLong,
+        )(i)?;
+        Ok((i, (version, status, status_text)))
+    })(i)
+}
 
-    let mut res = Response {
-        status,
-        status_text,
-        headers: Default::default(),
+// Looks like `GET /index.html HTTP/1.1\r\n`
+pub fn request(i: &[u8]) -> IResult<&[u8], Request<'_>, ParseError> {
+    request_with_limits(i, &Default::default())
+}
+
+/// Like [request], with custom limits
+pub fn request_with_limits<'a>(
+    i: &'a [u8],
+    limits: &ParserLimits,
+) -> IResult<&'a [u8], Request<'a>, ParseError> {
+    RequestParser::with_limits(*limits).parse(i)
+}
+
+/// Parses a request line, returning the method, target and version
+fn request_line(i: &[u8]) -> RawResult<'_, (&str, &str, Version)> {
+    with_kind(ParseErrorKind::InvalidRequestLine, |i| {
+        let (i, method) = map_res(
+            terminated(take_while1(is_tchar), tag(" ")),
+            std::str::from_utf8,
+        )(i)?;
+        let (i, target) = map_res(
+            terminated(take_while1(|c: u8| c.is_ascii_graphic()), tag(" ")),
+            std::str::from_utf8,
+        )(i)?;
+        let (i, version) = terminated(version, tag(CRLF))(i)?;
+        Ok((i, (method, target, version)))
+    })(i)
+}
+
+/// Enforces [ParserLimits::max_head_len] on the result of parsing a head that
+/// starts at `start`: a head that's still incomplete after that many bytes is
+/// just as much of a problem as one that's complete, but too large.
+fn head_limit<'a, T>(
+    start: &'a [u8],
+    limits: &ParserLimits,
+    res: RawResult<'a, T>,
+) -> RawResult<'a, T> {
+    let head_len = match &res {
+        Ok((i, _)) => start.offset(i),
+        Err(nom::Err::Incomplete(_)) => start.len(),
+        Err(_) => return res,
     };
 
-    let mut i = i;
-    loop {
-        if let (i, Some(_)) = opt(tag(CRLF))(i)? {
-            // end of headers
-            return Ok((i, res));
+    if head_len > limits.max_head_len {
+        return Err(nom::Err::Failure(RawError::new(
+            start,
+            ParseErrorKind::HeadTooLarge,
+        )));
+    }
+    res
+}
+
+impl Request<'_> {
+    /// Decides how the body of this request is delimited. Unlike responses,
+    /// requests can't be close-delimited: no framing headers means no body.
+    /// See https://httpwg.org/specs/rfc9112.html#message.body.length
+    ///
+    /// Requests with both `transfer-encoding` and `content-length` are
+    /// rejected rather than read as chunked, as allowed by
+    /// https://httpwg.org/specs/rfc9112.html#body.content-length
+    pub fn framing(&self) -> Result<Framing, HeaderError> {
+        if self.headers.contains("transfer-encoding") {
+            if self.headers.contains("content-length") {
+                return Err(HeaderError::AmbiguousFraming);
+            }
+            // there's no way to find the end of the body otherwise
+            if self.version == Version::Http11 && self.headers.is_chunked() {
+                return Ok(Framing::Chunked);
+            }
+            return Err(HeaderError::InvalidTransferEncoding);
         }
 
-        let (i2, (name, value)) = header(i)?;
-        res.headers.push((name, value));
-        i = i2;
+        match self.headers.content_length()? {
+            Some(content_length) => Ok(Framing::ContentLength(content_length)),
+            None => Ok(Framing::None),
+        }
+    }
+}
+
+impl<'a> Response<'a> {
+    /// Returns the reason phrase as a string, or `None` if it isn't valid
+    /// UTF-8. Nothing should depend on it anyway, see
+    /// https://httpwg.org/specs/rfc9112.html#status.line
+    pub fn status_text_str(&self) -> Option<&'a str> {
+        std::str::from_utf8(self.status_text).ok()
+    }
+
+    /// Returns true for 1xx responses, which are followed by another response
+    /// to the same request, except for `101 Switching Protocols`, after which
+    /// the connection speaks something else. See
+    /// https://httpwg.org/specs/rfc9110.html#status.1xx
+    pub fn is_interim(&self) -> bool {
+        (100..200).contains(&self.status) && self.status != 101
+    }
+
+    /// Decides how the body of this response is delimited. Note that responses
+    /// to `HEAD` requests never have a body, whatever their headers say: that's
+    /// up to the caller to handle.
+    pub fn framing(&self) -> Result<Framing, HeaderError> {
+        if matches!(self.status, 100..=199 | 204 | 304) {
+            return Ok(Framing::None);
+        }
+
+        if self.headers.contains("transfer-encoding") {
+            // HTTP/1.0 doesn't have transfer encodings, so the framing is
+            // faulty and the only safe thing to do is to read until close.
+            // Same if chunked isn't the last coding applied.
+            if self.version == Version::Http11 && self.headers.is_chunked() {
+                return Ok(Framing::Chunked);
+            }
+            return Ok(Framing::CloseDelimited);
+        }
+
+        match self.headers.content_length()? {
+            Some(content_length) => Ok(Framing::ContentLength(content_length)),
+            None => Ok(Framing::CloseDelimited),
+        }
+    }
+
+    /// Returns the target of a redirect (301, 302, 303, 307 or 308), as sent:
+    /// it may be relative, see [crate::redirect::Redirects::follow].
+    pub fn location(&self) -> Option<&'a str> {
+        if !matches!(self.status, 301 | 302 | 303 | 307 | 308) {
+            return None;
+        }
+        self.headers.get("location").filter(|l| !l.is_empty())
+    }
+
+    /// Returns true if the connection can be reused for another request once
+    /// the body of this response has been read. HTTP/1.1 connections are
+    /// persistent by default, HTTP/1.0 connections only if the server
+    /// explicitly says so.
+    pub fn keep_alive(&self) -> bool {
+        if matches!(self.framing(), Ok(Framing::CloseDelimited) | Err(_)) {
+            return false;
+        }
+
+        match self.version {
+            Version::Http10 => self.headers.has_connection_option("keep-alive"),
+            Version::Http11 => !self.headers.has_connection_option("close"),
+        }
     }
 }
 
 /// Parses a single header line
-fn header(i: &[u8]) -> IResult<&[u8], (&str, &str)> {
-    let (i, name) = map_res(terminated(take_until(":"), tag(":")), std::str::from_utf8)(i)?;
-    let (i, value) = map_res(
-        preceded(ws, terminated(take_until(CRLF), tag(CRLF))),
-        std::str::from_utf8,
-    )(i)?;
+fn header<'a>(i: &'a [u8], limits: &ParserLimits) -> RawResult<'a, Header<'a>> {
+    let (i, line) = line(limits.max_header_line_len, ParseErrorKind::HeaderTooLarge)(i)?;
+
+    let colon = line.iter().position(|&c| c == b':').unwrap_or(line.len());
+    let (name, value) = line.split_at(colon);
+    if name.is_empty() || !name.iter().copied().all(is_tchar) || value.is_empty() {
+        return Err(nom::Err::Error(RawError::new(
+            name,
+            ParseErrorKind::InvalidHeaderName,
+        )));
+    }
+
+    // skip the colon, and optional whitespace on either side of the value
+    let value = trim_ows(&value[1..]);
+    if let Some(pos) = value.iter().position(|&c| !is_field_vchar(c)) {
+        return Err(nom::Err::Error(RawError::new(
+            &value[pos..],
+            ParseErrorKind::InvalidHeaderValue,
+        )));
+    }
 
-    Ok((i, (name, value)))
+    // `is_tchar` only lets ASCII through
+    let name = std::str::from_utf8(name).unwrap();
+    Ok((i, Header { name, value }))
 }
 
-/// Parses whitespace (not including newlines)
-fn ws(i: &[u8]) -> IResult<&[u8], ()> {
-    let (i, _) = take_while1(|c| c == b' ')(i)?;
-    Ok((i, ()))
+/// Takes everything up to the next CRLF, and the CRLF itself, giving up with
+/// an error of the given kind if there's no CRLF within `max` bytes.
+fn line<'a>(max: usize, kind: ParseErrorKind) -> impl FnMut(&'a [u8]) -> RawResult<'a, &'a [u8]> {
+    move |i: &'a [u8]| {
+        let window = &i[..std::cmp::min(i.len(), max + CRLF.len())];
+        match window
+            .windows(CRLF.len())
+            .position(|w| w == CRLF.as_bytes())
+        {
+            Some(end) => Ok((&i[end + CRLF.len()..], &i[..end])),
+            None if window.len() == max + CRLF.len() => {
+                Err(nom::Err::Failure(RawError::new(i, kind)))
+            }
+            None => Err(nom::Err::Incomplete(nom::Needed::Unknown)),
+        }
+    }
 }
 
-/// Parses text as a u16
-fn u16_text(i: &[u8]) -> IResult<&[u8], u16> {
-    let f = take_while1(is_digit);
+/// Parses an HTTP/1.x version, like `HTTP/1.1`. Minor versions above 1 are
+/// treated as HTTP/1.1, since they're meant to be compatible with it, see
+/// https://httpwg.org/specs/rfc9112.html#http.version
+fn version(i: &[u8]) -> RawResult<'_, Version> {
+    let (i, _) = tag("HTTP/1.")(i)?;
+    let (i, minor) = take_while_m_n(1, 1, is_digit)(i)?;
+    match minor {
+        b"0" => Ok((i, Version::Http10)),
+        _ => Ok((i, Version::Http11)),
+    }
+}
+
+/// Parses a three-digit status code, like `200`
+fn status_code(i: &[u8]) -> RawResult<'_, u16> {
+    let f = take_while_m_n(3, 3, is_digit);
     let f = map_res(f, std::str::from_utf8);
     let mut f = map_res(f, |s| s.parse());
     f(i)
 }
+
+/// Parses whitespace (not including newlines)
+fn ws(i: &[u8]) -> RawResult<'_, ()> {
+    let (i, _) = take_while1(|c| c == b' ')(i)?;
+    Ok((i, ()))
+}
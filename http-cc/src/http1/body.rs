@@ -0,0 +1,224 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BytesMut};
+use nom::Offset;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use super::{Chunk, ChunkedDecoder, Framing, Response};
+use crate::headers::{Header, HeaderError, HeaderMap};
+
+/// Reads the body of a response from a stream, following the framing given
+/// by the response headers, so that it can be consumed with
+/// [tokio::io::AsyncReadExt] or [tokio::io::copy] without buffering all of it.
+///
+/// Once the body is done, it returns EOF (a read of 0 bytes), even if the
+/// underlying stream has more data.
+pub struct BodyReader<S> {
+    stream: S,
+    buf: BytesMut,
+    decoder: BodyDecoder,
+}
+
+impl<S> BodyReader<S> {
+    /// `leftover` is whatever was read past the end of the response head
+    pub fn new(stream: S, leftover: &[u8], framing: Framing) -> Self {
+        Self {
+            stream,
+            buf: BytesMut::from(leftover),
+            decoder: BodyDecoder::new(framing),
+        }
+    }
+
+    /// Picks the framing from the response headers, see [Response::framing]
+    pub fn from_response(
+        stream: S,
+        leftover: &[u8],
+        res: &Response<'_>,
+    ) -> Result<Self, HeaderError> {
+        Ok(Self::new(stream, leftover, res.framing()?))
+    }
+
+    /// Returns true once the whole body has been read
+    pub fn is_done(&self) -> bool {
+        self.decoder.is_done()
+    }
+
+    /// Returns the trailer fields, once the whole body has been read
+    pub fn trailers(&self) -> Option<HeaderMap<'_>> {
+        self.decoder.trailers()
+    }
+
+    /// Returns the underlying stream, and whatever was read past the end of the
+    /// body (if it's done).
+    pub fn into_inner(self) -> (S, BytesMut) {
+        (self.stream, self.buf)
+    }
+}
+
+impl<S> AsyncRead for BodyReader<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.decoder
+            .poll_read(cx, Pin::new(&mut this.stream), &mut this.buf, out)
+    }
+}
+
+/// The framing state of a body being read. This doesn't own the stream or
+/// the read buffer, so that connection types can keep those around across
+/// several responses.
+#[derive(Debug)]
+pub(crate) struct BodyDecoder {
+    state: State,
+
+    /// How many bytes at the start of the read buffer are body data that
+    /// hasn't been handed out yet.
+    ready: usize,
+
+    /// Trailer fields, received after the last chunk
+    trailers: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+enum State {
+    /// This many bytes are left
+    Length(u64),
+    Chunked(ChunkedDecoder),
+    /// Everything until EOF is body
+    Close,
+    Done,
+}
+
+impl BodyDecoder {
+    pub(crate) fn new(framing: Framing) -> Self {
+        let state = match framing {
+            Framing::None | Framing::ContentLength(0) => State::Done,
+            Framing::ContentLength(len) => State::Length(len),
+            Framing::Chunked => State::Chunked(ChunkedDecoder::new()),
+            Framing::CloseDelimited => State::Close,
+        };
+        Self {
+            state,
+            ready: 0,
+            trailers: Default::default(),
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self.state, State::Done) && self.ready == 0
+    }
+
+    /// Returns the trailer fields once the body is done, see [Chunk::End].
+    /// Bodies that aren't chunked never have any.
+    pub(crate) fn trailers(&self) -> Option<HeaderMap<'_>> {
+        if !self.is_done() {
+            return None;
+        }
+        Some(
+            self.trailers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+        )
+    }
+
+    /// Fills `out` with body data, taking it from `buf` first and reading
+    /// from `stream` into `buf` when that runs out.
+    pub(crate) fn poll_read<S>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut stream: Pin<&mut S>,
+        buf: &mut BytesMut,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>>
+    where
+        S: AsyncRead + ?Sized,
+    {
+        loop {
+            if self.ready > 0 {
+                let n = std::cmp::min(self.ready, out.remaining());
+                out.put_slice(&buf[..n]);
+                buf.advance(n);
+                self.ready -= n;
+                return Poll::Ready(Ok(()));
+            }
+
+            if !buf.is_empty() {
+                match &mut self.state {
+                    State::Done => return Poll::Ready(Ok(())),
+                    State::Length(remaining) => {
+                        let n = std::cmp::min(*remaining, buf.len() as u64);
+                        self.ready = n as usize;
+                        *remaining -= n;
+                        if *remaining == 0 {
+                            self.state = State::Done;
+                        }
+                        continue;
+                    }
+                    State::Close => {
+                        self.ready = buf.len();
+                        continue;
+                    }
+                    State::Chunked(decoder) => match decoder.decode(&buf[..]) {
+                        Ok((rest, chunk)) => {
+                            match chunk {
+                                Chunk::Data(data) => {
+                                    // drop the chunk framing, so that the data
+                                    // is at the start of the buffer.
+                                    let (framing_len, data_len) = (buf.offset(data), data.len());
+                                    buf.advance(framing_len);
+                                    self.ready = data_len;
+                                }
+                                Chunk::End(trailers) => {
+                                    self.trailers = trailers
+                                        .iter()
+                                        .map(|h| (h.name.to_owned(), h.value.to_owned()))
+                                        .collect();
+                                    let consumed = buf.offset(rest);
+                                    buf.advance(consumed);
+                                    self.state = State::Done;
+                                }
+                            }
+                            continue;
+                        }
+                        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                            return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e)));
+                        }
+                        Err(nom::Err::Incomplete(_)) => {
+                            // need to read more
+                        }
+                    },
+                }
+            } else if let State::Done = self.state {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut rd_buf = [0u8; 4096];
+            let mut rd_buf = ReadBuf::new(&mut rd_buf);
+            futures::ready!(stream.as_mut().poll_read(cx, &mut rd_buf))?;
+            if rd_buf.filled().is_empty() {
+                return match self.state {
+                    State::Close => {
+                        self.state = State::Done;
+                        Poll::Ready(Ok(()))
+                    }
+                    _ => Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "peer closed connection during body",
+                    ))),
+                };
+            }
+            buf.extend_from_slice(rd_buf.filled());
+        }
+    }
+}
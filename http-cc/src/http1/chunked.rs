@@ -0,0 +1,205 @@
+use nom::{
+    branch::alt,
+    bytes::streaming::{tag, take_while, take_while1},
+    character::{is_hex_digit, streaming::char},
+    combinator::{map_res, opt, recognize},
+    multi::many0_count,
+    sequence::{delimited, pair, tuple},
+    IResult, Needed,
+};
+
+use super::{header, line, ParserLimits, CRLF};
+use crate::{
+    error::{finish, with_kind, ParseError, ParseErrorKind, RawError, RawResult},
+    headers::{is_tchar, HeaderMap},
+};
+
+/// A piece of a chunked body, as returned by [ChunkedDecoder::decode]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    /// Some body data. This may be only part of a chunk, if the rest of it
+    /// hasn't been received yet.
+    Data(&'a [u8]),
+
+    /// The last chunk and the trailer section have been read, there is no
+    /// more body data. Trailer fields are like headers, but sent after the
+    /// body, see https://httpwg.org/specs/rfc9110.html#trailer.fields
+    End(HeaderMap<'a>),
+}
+
+/// Decodes a body sent with `transfer-encoding: chunked`, incrementally.
+///
+/// See https://httpwg.org/specs/rfc9112.html#chunked.encoding
+#[derive(Debug, Default)]
+pub struct ChunkedDecoder {
+    state: State,
+    limits: ParserLimits,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum State {
+    /// Expecting a chunk-size line
+    #[default]
+    Size,
+    /// In the middle of chunk data, with this many bytes left
+    Data(u64),
+    /// Expecting the CRLF that follows chunk data
+    DataEnd,
+    /// Past the last chunk, reading trailer fields
+    Trailers,
+    /// The whole body was read
+    Done,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Applies the given header limits to the trailer section, and
+    /// [ParserLimits::max_header_line_len] to chunk-size lines
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            state: Default::default(),
+            limits,
+        }
+    }
+
+    /// Returns true once the last chunk and trailer section have been decoded.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// Decodes as much as it can from the given slice, which should start
+    /// where the previous call left off.
+    ///
+    /// Like [super::response], this returns [nom::Err::Incomplete] when
+    /// more input is needed, in which case none of the input was consumed
+    /// and the same bytes (plus whatever was read since) should be passed
+    /// again. Chunk data is handed out as soon as it is available, so chunks
+    /// never need to be buffered whole.
+    pub fn decode<'a>(&mut self, i: &'a [u8]) -> IResult<&'a [u8], Chunk<'a>, ParseError> {
+        finish(i, self.raw_decode(i), ParseErrorKind::InvalidChunk)
+    }
+
+    fn raw_decode<'a>(&mut self, i: &'a [u8]) -> RawResult<'a, Chunk<'a>> {
+        // only commit state changes once we know we're returning `Ok`, so that
+        // an `Incomplete` error never swallows a chunk-size line.
+        let mut state = self.state;
+        let mut i = i;
+        let mut trailers = HeaderMap::new();
+
+        loop {
+            match state {
+                State::Size => {
+                    let (rest, size) = raw_chunk_size(i, self.limits.max_header_line_len)?;
+                    state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::Data(size)
+                    };
+                    i = rest;
+                }
+                State::Data(remaining) => {
+                    if i.is_empty() {
+                        return Err(nom::Err::Incomplete(Needed::Unknown));
+                    }
+
+                    let n = std::cmp::min(remaining, i.len() as u64) as usize;
+                    let (data, rest) = i.split_at(n);
+                    self.state = match remaining - n as u64 {
+                        0 => State::DataEnd,
+                        remaining => State::Data(remaining),
+                    };
+                    return Ok((rest, Chunk::Data(data)));
+                }
+                State::DataEnd => {
+                    let (rest, _) = with_kind(ParseErrorKind::InvalidChunk, tag(CRLF))(i)?;
+                    state = State::Size;
+                    i = rest;
+                }
+                State::Trailers => {
+                    if let (rest, Some(_)) = opt(tag(CRLF))(i)? {
+                        self.state = State::Done;
+                        return Ok((rest, Chunk::End(trailers)));
+                    }
+
+                    // trailer fields have the same syntax as header fields
+                    if trailers.len() == self.limits.max_headers {
+                        return Err(nom::Err::Failure(RawError::new(
+                            i,
+                            ParseErrorKind::TooManyHeaders,
+                        )));
+                    }
+                    let (rest, trailer) = header(i, &self.limits)?;
+                    trailers.push(trailer);
+                    i = rest;
+                }
+                // the trailers were returned already
+                State::Done => return Ok((i, Chunk::End(HeaderMap::new()))),
+            }
+        }
+    }
+}
+
+/// Parses a chunk-size line, like `1f4\r\n` or `0;name=value\r\n`. Chunk
+/// extensions are validated, but otherwise ignored. Lines longer than the
+/// default [ParserLimits::max_header_line_len] are rejected.
+pub fn chunk_size(i: &[u8]) -> IResult<&[u8], u64, ParseError> {
+    let max_line_len = ParserLimits::default().max_header_line_len;
+    finish(
+        i,
+        raw_chunk_size(i, max_line_len),
+        ParseErrorKind::InvalidChunk,
+    )
+}
+
+fn raw_chunk_size(i: &[u8], max_line_len: usize) -> RawResult<'_, u64> {
+    // there can be any number of extensions, so wait for the whole line (up
+    // to a limit) rather than re-parsing a growing one on every read
+    line(max_line_len, ParseErrorKind::InvalidChunk)(i)?;
+
+    let (i, size) = map_res(take_while1(is_hex_digit), |s| {
+        // `is_hex_digit` only lets ASCII through
+        u64::from_str_radix(std::str::from_utf8(s).unwrap(), 16)
+    })(i)?;
+    let (i, _) = many0_count(chunk_ext)(i)?;
+    let (i, _) = tag(CRLF)(i)?;
+
+    Ok((i, size))
+}
+
+/// Parses a single chunk extension, like `;name` or `; name="quoted value"`
+fn chunk_ext(i: &[u8]) -> RawResult<'_, ()> {
+    let (i, _) = tuple((
+        bws,
+        char(';'),
+        bws,
+        take_while1(is_tchar),
+        opt(tuple((
+            bws,
+            char('='),
+            bws,
+            alt((take_while1(is_tchar), quoted_string)),
+        ))),
+    ))(i)?;
+    Ok((i, ()))
+}
+
+/// Parses a quoted-string, see https://httpwg.org/specs/rfc9110.html#quoted.strings
+fn quoted_string(i: &[u8]) -> RawResult<'_, &[u8]> {
+    let qdtext = take_while1(|c| c != b'"' && c != b'\\' && c != b'\r' && c != b'\n');
+    let quoted_pair = recognize(pair(char('\\'), nom::bytes::streaming::take(1_usize)));
+    recognize(delimited(
+        char('"'),
+        many0_count(alt((qdtext, quoted_pair))),
+        char('"'),
+    ))(i)
+}
+
+/// Parses "bad" whitespace, which is optional, see
+/// https://httpwg.org/specs/rfc9110.html#whitespace
+fn bws(i: &[u8]) -> RawResult<'_, ()> {
+    let (i, _) = take_while(|c| c == b' ' || c == b'\t')(i)?;
+    Ok((i, ()))
+}
@@ -0,0 +1,457 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use color_eyre::eyre::eyre;
+use futures::FutureExt;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    time::Instant,
+};
+
+use super::{
+    body::BodyDecoder, response_with_limits, Framing, ParserLimits, RequestHead, Response,
+    ResponseParser, CRLF,
+};
+use crate::{headers::HeaderMap, timings::RequestTimings};
+
+/// An HTTP/1.1 connection to a server, that sends requests one after the
+/// other over the same stream, as long as the server lets us.
+///
+/// Each response body must be read (or dropped) before the next request is
+/// sent: whatever's left of it is drained then, since it's in the way of the
+/// next response.
+pub struct ClientConnection<S> {
+    stream: S,
+    limits: ParserLimits,
+
+    /// Bytes read from the stream but not consumed yet
+    buf: BytesMut,
+    /// The head of the last response, which [Response] borrows from
+    head: BytesMut,
+    /// The body of the last response, if it hasn't been read fully
+    body: Option<BodyDecoder>,
+    /// False once either side asked for the connection to be closed, or the
+    /// server closed it.
+    open: bool,
+    /// How long to wait for `100 Continue` before sending the body anyway
+    continue_timeout: Duration,
+
+    /// How long it took to set up the connection, until a request reports it
+    setup: RequestTimings,
+    /// Timings of the last request, except for its body
+    timings: RequestTimings,
+    /// When the first byte of the current response arrived
+    first_byte: Option<Instant>,
+    /// When the head of the last response was read, and when its body was
+    head_read: Option<Instant>,
+    body_finished: Option<Instant>,
+}
+
+impl<S> ClientConnection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(stream: S) -> Self {
+        Self::with_limits(stream, Default::default())
+    }
+
+    /// Uses the given limits when parsing response heads
+    pub fn with_limits(stream: S, limits: ParserLimits) -> Self {
+        Self {
+            stream,
+            limits,
+            buf: Default::default(),
+            head: Default::default(),
+            body: None,
+            open: true,
+            continue_timeout: Duration::from_secs(1),
+            setup: Default::default(),
+            timings: Default::default(),
+            first_byte: None,
+            head_read: None,
+            body_finished: None,
+        }
+    }
+
+    /// Sets how long to wait for a `100 Continue` response, when a request
+    /// has `expect: 100-continue`, before sending its body anyway. Some
+    /// servers never send one. Defaults to one second.
+    pub fn set_continue_timeout(&mut self, timeout: Duration) {
+        self.continue_timeout = timeout;
+    }
+
+    /// Hands over how long it took to set up the connection, so that it's
+    /// reported by the [Self::timings] of the next request. Only the setup
+    /// phases are kept, see [RequestTimings::setup].
+    pub fn set_connect_timings(&mut self, timings: RequestTimings) {
+        self.setup = timings.setup();
+    }
+
+    /// How long each phase of the last request sent with [Self::send] took.
+    /// The body is only timed once it was read fully.
+    pub fn timings(&self) -> RequestTimings {
+        let mut timings = self.timings;
+        if let (Some(start), Some(end)) = (self.head_read, self.body_finished) {
+            timings.response_body = Some(end - start);
+        }
+        timings
+    }
+
+    /// Returns false if the connection can't be used for more requests: the
+    /// last request or response had `connection: close`, the body was
+    /// close-delimited, or the server hung up.
+    ///
+    /// This only knows what happened during the last request: a server that
+    /// closed the connection while it was idle (after its keep-alive timeout,
+    /// usually) goes unnoticed, see [Self::is_reusable].
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Like [Self::is_open], but also checks (without blocking) whether the
+    /// server closed the connection since the last response was read. If the
+    /// last response body hasn't been read fully, this can't be told yet.
+    ///
+    /// There's still a window where the server closes the connection right
+    /// as a request is sent: that request fails, and can be retried on a new
+    /// connection if it's idempotent.
+    pub fn is_reusable(&mut self) -> bool {
+        let body_done = self.body.as_ref().map_or(true, |body| body.is_done());
+        if self.open && body_done {
+            self.check_idle();
+        }
+        self.open
+    }
+
+    /// Sends a request and reads the response head. `body` must match the
+    /// framing of `req`: it's sent as a single chunk if that's
+    /// [Framing::Chunked].
+    ///
+    /// Interim (1xx) responses are skipped, except for `101 Switching
+    /// Protocols`, after which the connection isn't HTTP anymore.
+    ///
+    /// If `req` has `expect: 100-continue`, only the head is sent at first,
+    /// and the body follows once the server answers `100 Continue`, or after
+    /// a timeout (see [Self::set_continue_timeout]). If the server sends a
+    /// final response instead, like 413 or 401, the body is never sent, and
+    /// the connection is closed afterwards, since the server may still be
+    /// expecting it. See https://httpwg.org/specs/rfc9110.html#field.expect
+    pub async fn send(
+        &mut self,
+        req: &RequestHead,
+        body: &[u8],
+    ) -> color_eyre::Result<(Response<'_>, Body<'_, S>)> {
+        if !self.open {
+            return Err(eyre!("connection is closed"));
+        }
+        self.drain().await?;
+        self.check_idle();
+        if !self.open {
+            return Err(eyre!("server closed the connection"));
+        }
+
+        let mut out = Vec::new();
+        req.encode(&mut out);
+        let head_len = out.len();
+        encode_body(req, body, &mut out)?;
+
+        let started = Instant::now();
+        let mut timings = std::mem::take(&mut self.setup);
+        let mut sent = None;
+        self.first_byte = None;
+        self.head_read = None;
+        self.body_finished = None;
+        let res = async {
+            if body.is_empty() || !expects_continue(req) {
+                self.write_all(&out).await?;
+                sent = Some(Instant::now());
+                return self.read_response(req).await;
+            }
+
+            let (head, body) = out.split_at(head_len);
+            self.write_all(head).await?;
+            let head_sent = Instant::now();
+            match tokio::time::timeout(self.continue_timeout, self.wait_continue(req)).await {
+                // got a final response, the body isn't wanted
+                Ok(Ok(Some(framing))) => {
+                    sent = Some(head_sent);
+                    self.open = false;
+                    return Ok(framing);
+                }
+                Ok(Err(e)) => return Err(e),
+                // got a 100 Continue, or no answer in time
+                Ok(Ok(None)) | Err(_) => {}
+            }
+            self.write_all(body).await?;
+            let body_sent = Instant::now();
+            sent = Some(body_sent);
+            // the first byte is the one of the response to the whole
+            // request, not of the `100 Continue`. if that response came
+            // along with it, it's only seen now.
+            self.first_byte = (!self.buf.is_empty()).then_some(body_sent);
+            self.read_response(req).await
+        };
+        let res = res.await;
+
+        let head_read = Instant::now();
+        timings.request_send = sent.map(|sent| sent - started);
+        if let Some(first_byte) = self.first_byte {
+            timings.time_to_first_byte = Some(first_byte - started);
+            timings.response_head = res.is_ok().then(|| head_read - first_byte);
+        }
+        self.timings = timings;
+        let framing = match res {
+            Ok(framing) => framing,
+            Err(e) => {
+                // there's no telling what state the connection is in
+                self.open = false;
+                return Err(e);
+            }
+        };
+        self.head_read = Some(head_read);
+        self.start_body(framing)
+    }
+
+    /// Returns the underlying stream, and whatever was read past the end of
+    /// the last response.
+    pub fn into_inner(self) -> (S, BytesMut) {
+        (self.stream, self.buf)
+    }
+
+    /// Reads response heads until the final one for `req`, and returns the
+    /// framing of its body, which starts at the beginning of `self.buf`.
+    pub(super) async fn read_response(&mut self, req: &RequestHead) -> color_eyre::Result<Framing> {
+        loop {
+            self.read_head().await?;
+            if let Some(framing) = self.final_response(req)? {
+                return Ok(framing);
+            }
+        }
+    }
+
+    /// Reads interim responses until a `100 Continue`, in which case it
+    /// returns `None`, or a final response, whose framing it returns.
+    async fn wait_continue(&mut self, req: &RequestHead) -> color_eyre::Result<Option<Framing>> {
+        loop {
+            self.read_head().await?;
+            let (_, res) = response_with_limits(&self.head, &self.limits)?;
+            if res.status == 100 {
+                return Ok(None);
+            }
+            if let Some(framing) = self.final_response(req)? {
+                return Ok(Some(framing));
+            }
+        }
+    }
+
+    /// Looks at the response head in `self.head`: returns `None` if it's an
+    /// interim response, the framing of its body otherwise.
+    fn final_response(&mut self, req: &RequestHead) -> color_eyre::Result<Option<Framing>> {
+        let (_, res) = response_with_limits(&self.head, &self.limits)?;
+        if res.status == 101 {
+            self.open = false;
+            return Ok(Some(Framing::None));
+        }
+        if res.is_interim() {
+            return Ok(None);
+        }
+
+        let framing = if req.method() == "HEAD" {
+            Framing::None
+        } else {
+            res.framing()?
+        };
+        self.open = res.keep_alive() && !req.headers().has_connection_option("close");
+        Ok(Some(framing))
+    }
+
+    /// Returns the response read by [Self::read_response], and its body
+    pub(super) fn start_body(
+        &mut self,
+        framing: Framing,
+    ) -> color_eyre::Result<(Response<'_>, Body<'_, S>)> {
+        let Self {
+            stream,
+            limits,
+            buf,
+            head,
+            body,
+            body_finished,
+            ..
+        } = self;
+        // this was parsed by `read_response`, so it can't fail
+        let (_, res) = response_with_limits(head, limits)?;
+        let body = Body {
+            stream,
+            buf,
+            decoder: body.insert(BodyDecoder::new(framing)),
+            finished: Some(body_finished),
+        };
+        Ok((res, body))
+    }
+
+    pub(super) async fn write_all(&mut self, out: &[u8]) -> color_eyre::Result<()> {
+        self.stream.write_all(out).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    pub(super) fn set_closed(&mut self) {
+        self.open = false;
+    }
+
+    /// Reads a response head into `self.head`, leaving anything after it in
+    /// `self.buf`.
+    async fn read_head(&mut self) -> color_eyre::Result<()> {
+        let mut parser = ResponseParser::with_limits(self.limits);
+        loop {
+            match parser.parse(&self.buf) {
+                Ok((rest, _)) => {
+                    let head_len = self.buf.len() - rest.len();
+                    self.head = self.buf.split_to(head_len);
+                    return Ok(());
+                }
+                Err(nom::Err::Incomplete(_)) => {}
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => return Err(e.into()),
+            }
+
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(if self.buf.is_empty() {
+                    eyre!("server closed connection before responding")
+                } else {
+                    eyre!("unexpected EOF (server closed connection during headers)")
+                });
+            }
+            // bytes that were buffered already (pipelined responses, or a
+            // response that came with a `100 Continue`) didn't arrive now,
+            // so only actual reads count
+            self.first_byte.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// Marks the connection as closed if the server hung up, or sent
+    /// something, while no request was in flight (a `408 Request Timeout`
+    /// before closing, for example). Doesn't wait for anything.
+    fn check_idle(&mut self) {
+        if !self.buf.is_empty() {
+            self.open = false;
+            return;
+        }
+        // nothing to read is the only good outcome: anything else is EOF, an
+        // error, or data no request asked for
+        if self.stream.read_buf(&mut self.buf).now_or_never().is_some() {
+            self.open = false;
+        }
+    }
+
+    /// Reads whatever's left of the previous response body, and throws it
+    /// away.
+    pub(super) async fn drain(&mut self) -> color_eyre::Result<()> {
+        if let Some(decoder) = self.body.as_mut() {
+            let mut body = Body {
+                stream: &mut self.stream,
+                buf: &mut self.buf,
+                decoder,
+                finished: None,
+            };
+            if let Err(e) = tokio::io::copy(&mut body, &mut tokio::io::sink()).await {
+                self.open = false;
+                return Err(e.into());
+            }
+        }
+        self.body = None;
+        Ok(())
+    }
+}
+
+/// Returns true if the request asks the server for a `100 Continue` before
+/// its body is sent
+pub(super) fn expects_continue(req: &RequestHead) -> bool {
+    req.headers()
+        .get_list("expect")
+        .any(|v| v.eq_ignore_ascii_case(b"100-continue"))
+}
+
+/// Serializes a request and its body. `body` must match the framing of `req`:
+/// it's sent as a single chunk if that's [Framing::Chunked].
+pub(super) fn encode_request(
+    req: &RequestHead,
+    body: &[u8],
+    out: &mut Vec<u8>,
+) -> color_eyre::Result<()> {
+    req.encode(out);
+    encode_body(req, body, out)
+}
+
+/// Serializes the body of a request, see [encode_request]
+fn encode_body(req: &RequestHead, body: &[u8], out: &mut Vec<u8>) -> color_eyre::Result<()> {
+    match req.framing() {
+        Framing::None if body.is_empty() => {}
+        Framing::ContentLength(len) if len == body.len() as u64 => out.extend_from_slice(body),
+        Framing::Chunked => {
+            if !body.is_empty() {
+                out.extend_from_slice(format!("{:x}{CRLF}", body.len()).as_bytes());
+                out.extend_from_slice(body);
+                out.extend_from_slice(CRLF.as_bytes());
+            }
+            out.extend_from_slice(format!("0{CRLF}{CRLF}").as_bytes());
+        }
+        framing => {
+            return Err(eyre!(
+                "{} bytes of body don't match framing {framing:?}",
+                body.len()
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// The body of a response received on a [ClientConnection] (or of a request
+/// received by a [super::Server]). It reads until the end of the body, then
+/// returns EOF.
+pub struct Body<'a, S> {
+    pub(super) stream: &'a mut S,
+    pub(super) buf: &'a mut BytesMut,
+    pub(super) decoder: &'a mut BodyDecoder,
+    /// Where to note when the body was read fully, for timings
+    pub(super) finished: Option<&'a mut Option<Instant>>,
+}
+
+impl<S> Body<'_, S> {
+    /// Returns true once the whole body has been read
+    pub fn is_done(&self) -> bool {
+        self.decoder.is_done()
+    }
+
+    /// Returns the trailer fields, once the whole body has been read
+    pub fn trailers(&self) -> Option<HeaderMap<'_>> {
+        self.decoder.trailers()
+    }
+}
+
+impl<S> AsyncRead for Body<'_, S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = this
+            .decoder
+            .poll_read(cx, Pin::new(&mut *this.stream), this.buf, out);
+        if this.decoder.is_done() {
+            if let Some(finished) = this.finished.as_deref_mut() {
+                finished.get_or_insert_with(Instant::now);
+            }
+        }
+        res
+    }
+}
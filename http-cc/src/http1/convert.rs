@@ -0,0 +1,132 @@
+use super::{Request, Response, Version};
+use crate::{
+    headers::{Header, HeaderMap},
+    ConvertError,
+};
+
+impl From<Version> for http::Version {
+    fn from(version: Version) -> Self {
+        match version {
+            Version::Http10 => http::Version::HTTP_10,
+            Version::Http11 => http::Version::HTTP_11,
+        }
+    }
+}
+
+impl TryFrom<http::Version> for Version {
+    type Error = ConvertError;
+
+    fn try_from(version: http::Version) -> Result<Self, Self::Error> {
+        match version {
+            http::Version::HTTP_10 => Ok(Version::Http10),
+            http::Version::HTTP_11 => Ok(Version::Http11),
+            version => Err(ConvertError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+impl TryFrom<&HeaderMap<'_>> for http::HeaderMap {
+    type Error = ConvertError;
+
+    fn try_from(headers: &HeaderMap<'_>) -> Result<Self, Self::Error> {
+        let mut map = http::HeaderMap::with_capacity(headers.len());
+        for header in headers {
+            map.append(
+                http::header::HeaderName::from_bytes(header.name.as_bytes())?,
+                http::HeaderValue::from_bytes(header.value)?,
+            );
+        }
+        Ok(map)
+    }
+}
+
+impl<'a> From<&'a http::HeaderMap> for HeaderMap<'a> {
+    fn from(map: &'a http::HeaderMap) -> Self {
+        map.iter()
+            .map(|(name, value)| Header {
+                name: name.as_str(),
+                value: value.as_bytes(),
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<&Response<'_>> for http::Response<()> {
+    type Error = ConvertError;
+
+    fn try_from(res: &Response<'_>) -> Result<Self, Self::Error> {
+        let mut out = http::Response::builder()
+            .status(res.status)
+            .version(res.version.into())
+            .body(())?;
+        *out.headers_mut() = (&res.headers).try_into()?;
+        Ok(out)
+    }
+}
+
+impl<'a, B> TryFrom<&'a http::Response<B>> for Response<'a> {
+    type Error = ConvertError;
+
+    /// The reason phrase isn't part of [http::Response], so this uses the
+    /// canonical one for the status code.
+    fn try_from(res: &'a http::Response<B>) -> Result<Self, Self::Error> {
+        Ok(Response {
+            version: res.version().try_into()?,
+            status: res.status().as_u16(),
+            status_text: res
+                .status()
+                .canonical_reason()
+                .unwrap_or_default()
+                .as_bytes(),
+            headers: res.headers().into(),
+        })
+    }
+}
+
+impl TryFrom<&Request<'_>> for http::Request<()> {
+    type Error = ConvertError;
+
+    fn try_from(req: &Request<'_>) -> Result<Self, Self::Error> {
+        let mut out = http::Request::builder()
+            .method(req.method)
+            .uri(req.target)
+            .version(req.version.into())
+            .body(())?;
+        *out.headers_mut() = (&req.headers).try_into()?;
+        Ok(out)
+    }
+}
+
+impl<'a, B> TryFrom<&'a http::Request<B>> for Request<'a> {
+    type Error = ConvertError;
+
+    /// The request target is taken from the path and query of the URI. If the
+    /// URI has an authority but there's no `host` header, one is added, since
+    /// HTTP/1.1 requires it.
+    fn try_from(req: &'a http::Request<B>) -> Result<Self, Self::Error> {
+        let uri = req.uri();
+        let target = match uri.path_and_query() {
+            Some(pq) => pq.as_str(),
+            // authority-form, used by CONNECT
+            None if req.method() == http::Method::CONNECT => {
+                uri.authority().map(|a| a.as_str()).unwrap_or_default()
+            }
+            None => "/",
+        };
+
+        let mut headers = HeaderMap::from(req.headers());
+        if let (Some(authority), false) = (uri.authority(), headers.contains("host")) {
+            headers.push(Header {
+                name: "host",
+                value: authority.as_str().as_bytes(),
+            });
+        }
+
+        Ok(Request {
+            method: req.method().as_str(),
+            target,
+            version: req.version().try_into()?,
+            headers,
+        })
+    }
+}
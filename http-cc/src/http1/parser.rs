@@ -0,0 +1,224 @@
+use std::ops::Range;
+
+use nom::{bytes::streaming::tag, combinator::opt, IResult, Offset};
+
+use super::{
+    head_limit, header, request_line, status_line, ParserLimits, Request, Response, Version, CRLF,
+};
+use crate::{
+    error::{finish, ParseError, ParseErrorKind, RawError, RawResult},
+    headers::{Header, HeaderMap},
+};
+
+/// Parses a response head incrementally, as it's being read: every call
+/// picks up where the previous one left off, instead of re-parsing the
+/// status line and every header line from the start of the buffer.
+///
+/// Only complete lines are remembered, so a single line arriving in many
+/// small reads is still scanned several times, but that's bounded by
+/// [ParserLimits::max_header_line_len].
+#[derive(Debug, Default)]
+pub struct ResponseParser {
+    limits: ParserLimits,
+    status_line: Option<StatusLine>,
+    lines: HeaderLines,
+}
+
+#[derive(Debug)]
+struct StatusLine {
+    version: Version,
+    status: u16,
+    status_text: Range<usize>,
+}
+
+/// Like [ResponseParser], for request heads
+#[derive(Debug, Default)]
+pub struct RequestParser {
+    limits: ParserLimits,
+    request_line: Option<RequestLine>,
+    lines: HeaderLines,
+}
+
+#[derive(Debug)]
+struct RequestLine {
+    method: Range<usize>,
+    target: Range<usize>,
+    version: Version,
+}
+
+/// The header lines parsed so far, shared by both parsers
+#[derive(Debug, Default)]
+struct HeaderLines {
+    /// How many bytes of the buffer have been parsed so far, start line
+    /// included
+    pos: usize,
+    /// Name and value of the headers parsed so far, as ranges in the buffer
+    headers: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl ResponseParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// Parses as much of the response head as it can. `buf` must start with
+    /// the same bytes every time: it's fine (and expected) for it to grow
+    /// between calls, or to move in memory, but not to be modified.
+    ///
+    /// Like [super::response], this returns [nom::Err::Incomplete] until the
+    /// whole head is there. Once it returns a [Response], the parser is reset
+    /// and can be used for the next response, in a fresh buffer.
+    pub fn parse<'a>(&mut self, buf: &'a [u8]) -> IResult<&'a [u8], Response<'a>, ParseError> {
+        let res = self.raw_parse(buf);
+        let res = head_limit(buf, &self.limits, res);
+        let res = finish(buf, res, ParseErrorKind::InvalidStatusLine);
+        if res.is_ok() {
+            self.reset();
+        }
+        res
+    }
+
+    /// Forgets about any progress made so far
+    pub fn reset(&mut self) {
+        self.status_line = None;
+        self.lines.reset();
+    }
+
+    fn raw_parse<'a>(&mut self, buf: &'a [u8]) -> RawResult<'a, Response<'a>> {
+        let status_line = match self.status_line.take() {
+            Some(status_line) => status_line,
+            None => {
+                let (i, (version, status, status_text)) = status_line(buf, &self.limits)?;
+                self.lines.pos = buf.offset(i);
+                StatusLine {
+                    version,
+                    status,
+                    status_text: range_of(buf, status_text),
+                }
+            }
+        };
+        // if we return early from now on, we'll resume from the headers
+        let status_line = self.status_line.insert(status_line);
+
+        let (rest, headers) = self.lines.parse(buf, &self.limits)?;
+        let res = Response {
+            version: status_line.version,
+            status: status_line.status,
+            status_text: &buf[status_line.status_text.clone()],
+            headers,
+        };
+        Ok((rest, res))
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// Parses as much of the request head as it can, with the same rules as
+    /// [ResponseParser::parse]
+    pub fn parse<'a>(&mut self, buf: &'a [u8]) -> IResult<&'a [u8], Request<'a>, ParseError> {
+        let res = self.raw_parse(buf);
+        let res = head_limit(buf, &self.limits, res);
+        let res = finish(buf, res, ParseErrorKind::InvalidRequestLine);
+        if res.is_ok() {
+            self.reset();
+        }
+        res
+    }
+
+    /// Forgets about any progress made so far
+    pub fn reset(&mut self) {
+        self.request_line = None;
+        self.lines.reset();
+    }
+
+    fn raw_parse<'a>(&mut self, buf: &'a [u8]) -> RawResult<'a, Request<'a>> {
+        let request_line = match self.request_line.take() {
+            Some(request_line) => request_line,
+            None => {
+                let (i, (method, target, version)) = request_line(buf)?;
+                self.lines.pos = buf.offset(i);
+                RequestLine {
+                    method: range_of(buf, method.as_bytes()),
+                    target: range_of(buf, target.as_bytes()),
+                    version,
+                }
+            }
+        };
+        let request_line = self.request_line.insert(request_line);
+
+        let (rest, headers) = self.lines.parse(buf, &self.limits)?;
+        let req = Request {
+            // both were validated when parsing the line
+            method: std::str::from_utf8(&buf[request_line.method.clone()]).unwrap(),
+            target: std::str::from_utf8(&buf[request_line.target.clone()]).unwrap(),
+            version: request_line.version,
+            headers,
+        };
+        Ok((rest, req))
+    }
+}
+
+impl HeaderLines {
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.headers.clear();
+    }
+
+    /// Parses header lines from where the last call stopped, up to (and
+    /// including) the empty line that ends them
+    fn parse<'a>(&mut self, buf: &'a [u8], limits: &ParserLimits) -> RawResult<'a, HeaderMap<'a>> {
+        let mut i = &buf[self.pos..];
+        loop {
+            if let (rest, Some(_)) = opt(tag(CRLF))(i)? {
+                // end of headers
+                let headers = self
+                    .headers
+                    .iter()
+                    .map(|(name, value)| Header {
+                        // this was validated when parsing the line
+                        name: std::str::from_utf8(&buf[name.clone()]).unwrap(),
+                        value: &buf[value.clone()],
+                    })
+                    .collect();
+                return Ok((rest, headers));
+            }
+
+            if self.headers.len() == limits.max_headers {
+                return Err(nom::Err::Failure(RawError::new(
+                    i,
+                    ParseErrorKind::TooManyHeaders,
+                )));
+            }
+            let (rest, header) = header(i, limits)?;
+            self.headers.push((
+                range_of(buf, header.name.as_bytes()),
+                range_of(buf, header.value),
+            ));
+            self.pos = buf.offset(rest);
+            i = rest;
+        }
+    }
+}
+
+/// Returns where `part` is within `buf`
+fn range_of(buf: &[u8], part: &[u8]) -> Range<usize> {
+    let start = buf.offset(part);
+    start..start + part.len()
+}
@@ -0,0 +1,148 @@
+use std::{collections::VecDeque, fmt, ops::Range};
+
+use color_eyre::eyre::eyre;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{
+    client::{encode_request, Body},
+    ClientConnection, RequestHead, Response,
+};
+
+/// A batch of requests written back-to-back on a [ClientConnection], whose
+/// responses are read in the same order, see
+/// https://httpwg.org/specs/rfc9112.html#pipelining
+///
+/// Like with [ClientConnection::send], each response body must be read (or
+/// dropped) before the next response: whatever's left of it is drained then.
+pub struct Pipeline<'c, S> {
+    conn: &'c mut ClientConnection<S>,
+    /// Requests that haven't been answered yet, in order
+    pending: VecDeque<RequestHead>,
+    /// How many requests were answered so far
+    answered: usize,
+}
+
+/// Returned by [Pipeline] when some requests will never get a response,
+/// usually because the server closed the connection midway.
+#[derive(Debug)]
+pub struct PipelineError {
+    /// Indices of the requests that weren't answered, in the order they were
+    /// written. Some of them may still have been processed by the server.
+    pub unanswered: Range<usize>,
+    pub error: color_eyre::Report,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "requests {}..{} were never answered: {}",
+            self.unanswered.start, self.unanswered.end, self.error
+        )
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+impl<S> ClientConnection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Writes all the requests, with their bodies (see
+    /// [ClientConnection::send]), without waiting for any response. Then the
+    /// responses can be read with [Pipeline::next].
+    ///
+    /// Only idempotent requests should be pipelined, since the ones that
+    /// don't get an answer may or may not have been processed.
+    ///
+    /// If any of the bodies doesn't match its request's framing, nothing is
+    /// written and all the requests are reported as unanswered.
+    pub async fn pipeline<'r, I>(&mut self, reqs: I) -> Result<Pipeline<'_, S>, PipelineError>
+    where
+        I: IntoIterator<Item = (&'r RequestHead, &'r [u8])>,
+    {
+        // everything is encoded before anything is written, so a request
+        // that can't be sent fails the whole batch, and the connection is
+        // left as it was
+        let reqs: Vec<_> = reqs.into_iter().collect();
+        let mut out = Vec::new();
+        for (req, body) in &reqs {
+            if let Err(error) = encode_request(req, body, &mut out) {
+                return Err(PipelineError {
+                    unanswered: 0..reqs.len(),
+                    error,
+                });
+            }
+        }
+        let pending: VecDeque<_> = reqs.iter().map(|&(req, _)| req.clone()).collect();
+
+        let res = async {
+            if !self.is_open() {
+                return Err(eyre!("connection is closed"));
+            }
+            self.drain().await?;
+            self.write_all(&out).await
+        };
+        if let Err(error) = res.await {
+            self.set_closed();
+            return Err(PipelineError {
+                unanswered: 0..pending.len(),
+                error,
+            });
+        }
+        Ok(Pipeline {
+            conn: self,
+            pending,
+            answered: 0,
+        })
+    }
+}
+
+impl<S> Pipeline<'_, S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Returns how many requests are still waiting for a response
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Reads the response to the next request, in the order they were
+    /// written. Returns `None` once all of them were answered, or after a
+    /// [PipelineError].
+    pub async fn next(&mut self) -> Option<Result<(Response<'_>, Body<'_, S>), PipelineError>> {
+        let req = self.pending.front()?;
+
+        let res = async {
+            if !self.conn.is_open() {
+                return Err(eyre!("server closed the connection"));
+            }
+            self.conn.drain().await?;
+            self.conn.read_response(req).await
+        };
+        let framing = match res.await {
+            Ok(framing) => framing,
+            Err(error) => {
+                self.conn.set_closed();
+                let unanswered = self.answered..self.answered + self.pending.len();
+                self.pending.clear();
+                return Some(Err(PipelineError { unanswered, error }));
+            }
+        };
+
+        self.pending.pop_front();
+        self.answered += 1;
+        Some(
+            self.conn
+                .start_body(framing)
+                .map_err(|error| PipelineError {
+                    unanswered: self.answered..self.answered,
+                    error,
+                }),
+        )
+    }
+}
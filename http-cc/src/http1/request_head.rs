@@ -0,0 +1,266 @@
+use std::fmt;
+
+use nom::{IResult, Offset};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::{request_with_limits, Framing, ParserLimits, Request, Version, CRLF};
+use crate::{
+    headers::{is_field_vchar, is_tchar, trim_ows, Header, HeaderMap},
+    ParseError, ParseErrorKind,
+};
+
+/// An owned, validated request head: request line and headers. Framing
+/// headers (`content-length`, `transfer-encoding`) are derived from
+/// [RequestHead::framing] rather than set by hand, so they can't disagree
+/// with the body that's actually sent.
+///
+/// Build one with [RequestHead::builder], or parse one with
+/// [RequestHead::parse].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHead {
+    method: String,
+    target: String,
+    version: Version,
+    headers: Vec<(String, Vec<u8>)>,
+    framing: Framing,
+}
+
+/// Returned by [RequestHeadBuilder::build] when something wouldn't make it
+/// onto the wire intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidHead {
+    InvalidMethod(String),
+    /// The target is empty, or contains spaces or control characters
+    InvalidTarget(String),
+    InvalidHeaderName(String),
+    /// The value contains CR, LF, NUL or other control characters, which
+    /// could be used to smuggle extra headers in
+    InvalidHeaderValue(String),
+    /// `content-length` and `transfer-encoding` are set from the framing, see
+    /// [RequestHeadBuilder::framing]
+    FramingHeader(String),
+    /// Requests can't be close-delimited
+    CloseDelimited,
+    /// HTTP/1.0 has no chunked transfer coding, so the body needs a length
+    ChunkedHttp10,
+}
+
+impl fmt::Display for InvalidHead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMethod(method) => write!(f, "invalid method {method:?}"),
+            Self::InvalidTarget(target) => write!(f, "invalid request target {target:?}"),
+            Self::InvalidHeaderName(name) => write!(f, "invalid header name {name:?}"),
+            Self::InvalidHeaderValue(name) => write!(f, "invalid value for header {name:?}"),
+            Self::FramingHeader(name) => {
+                write!(f, "{name:?} is set from the framing, not by hand")
+            }
+            Self::CloseDelimited => write!(f, "requests can't be close-delimited"),
+            Self::ChunkedHttp10 => write!(f, "HTTP/1.0 requests can't be chunked"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidHead {}
+
+/// Builds a [RequestHead]. Errors are reported by [RequestHeadBuilder::build],
+/// so that calls can be chained.
+#[derive(Debug)]
+pub struct RequestHeadBuilder {
+    head: RequestHead,
+    error: Option<InvalidHead>,
+}
+
+impl RequestHead {
+    /// Starts building an HTTP/1.1 request with no body
+    pub fn builder(method: &str, target: &str) -> RequestHeadBuilder {
+        let mut builder = RequestHeadBuilder {
+            head: RequestHead {
+                method: method.to_owned(),
+                target: target.to_owned(),
+                version: Version::Http11,
+                headers: Default::default(),
+                framing: Framing::None,
+            },
+            error: None,
+        };
+
+        if method.is_empty() || !method.bytes().all(is_tchar) {
+            builder.error = Some(InvalidHead::InvalidMethod(method.to_owned()));
+        } else if target.is_empty() || !target.bytes().all(|c| c.is_ascii_graphic()) {
+            builder.error = Some(InvalidHead::InvalidTarget(target.to_owned()));
+        }
+        builder
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Returns the headers, not including the framing headers
+    pub fn headers(&self) -> HeaderMap<'_> {
+        self.headers
+            .iter()
+            .map(|(name, value)| Header { name, value })
+            .collect()
+    }
+
+    /// Parses a request head, the inverse of [RequestHead::encode]. Framing
+    /// headers are taken out of the header list and turned into a [Framing].
+    pub fn parse(i: &[u8]) -> IResult<&[u8], Self, ParseError> {
+        Self::parse_with_limits(i, &Default::default())
+    }
+
+    /// Like [RequestHead::parse], with custom limits
+    pub fn parse_with_limits<'a>(
+        i: &'a [u8],
+        limits: &ParserLimits,
+    ) -> IResult<&'a [u8], Self, ParseError> {
+        let (rest, req) = request_with_limits(i, limits)?;
+        Ok((rest, Self::from_request(i, req)?))
+    }
+
+    /// Takes ownership of a request parsed from `i`, deriving its framing
+    pub(super) fn from_request(i: &[u8], req: Request<'_>) -> Result<Self, nom::Err<ParseError>> {
+        let framing = req.framing().map_err(|_| {
+            // point at the framing header that doesn't make sense
+            let value = req
+                .headers
+                .iter()
+                .find(|h| is_framing_header(h.name))
+                .map(|h| h.value)
+                .unwrap_or(i);
+            nom::Err::Failure(ParseError {
+                kind: ParseErrorKind::InvalidHeaderValue,
+                offset: i.offset(value),
+            })
+        })?;
+
+        let head = RequestHead {
+            method: req.method.to_owned(),
+            target: req.target.to_owned(),
+            version: req.version,
+            headers: req
+                .headers
+                .iter()
+                .filter(|h| !is_framing_header(h.name))
+                .map(|h| (h.name.to_owned(), h.value.to_owned()))
+                .collect(),
+            framing,
+        };
+        Ok(head)
+    }
+
+    /// Serializes the request line and headers, including framing headers,
+    /// and the empty line that ends the head.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let version = match self.version {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        };
+        out.extend_from_slice(self.method.as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(self.target.as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(version.as_bytes());
+        out.extend_from_slice(CRLF.as_bytes());
+
+        let mut header = |name: &[u8], value: &[u8]| {
+            out.extend_from_slice(name);
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value);
+            out.extend_from_slice(CRLF.as_bytes());
+        };
+        for (name, value) in &self.headers {
+            header(name.as_bytes(), value);
+        }
+        match self.framing {
+            Framing::ContentLength(len) => header(b"content-length", len.to_string().as_bytes()),
+            Framing::Chunked => header(b"transfer-encoding", b"chunked"),
+            Framing::None | Framing::CloseDelimited => {}
+        }
+
+        out.extend_from_slice(CRLF.as_bytes());
+    }
+
+    /// Writes the request head to an [AsyncWrite], in a single write.
+    pub async fn write(&self, w: &mut (dyn AsyncWrite + Unpin)) -> color_eyre::Result<()> {
+        let mut buf = Vec::with_capacity(256);
+        self.encode(&mut buf);
+        w.write_all(&buf).await?;
+        Ok(())
+    }
+}
+
+impl RequestHeadBuilder {
+    pub fn version(mut self, version: Version) -> Self {
+        self.head.version = version;
+        self
+    }
+
+    /// Adds a header. Names must be tokens, and values can't contain control
+    /// characters (line breaks in particular). Leading and trailing
+    /// whitespace is trimmed from values.
+    pub fn header(mut self, name: &str, value: impl AsRef<[u8]>) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        match check_header(name, value.as_ref()) {
+            Ok(header) => self.head.headers.push(header),
+            Err(e) => self.error = Some(e),
+        }
+        self
+    }
+
+    /// Sets how the body will be sent, which decides the framing headers.
+    /// Defaults to [Framing::None].
+    pub fn framing(mut self, framing: Framing) -> Self {
+        if let Framing::CloseDelimited = framing {
+            self.error.get_or_insert(InvalidHead::CloseDelimited);
+        }
+        self.head.framing = framing;
+        self
+    }
+
+    pub fn build(self) -> Result<RequestHead, InvalidHead> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        // checked here, since the version and framing can be set in any order
+        if self.head.version == Version::Http10 && self.head.framing == Framing::Chunked {
+            return Err(InvalidHead::ChunkedHttp10);
+        }
+        Ok(self.head)
+    }
+}
+
+/// Validates a header for [RequestHeadBuilder::header], trimming its value
+pub(super) fn check_header(name: &str, value: &[u8]) -> Result<(String, Vec<u8>), InvalidHead> {
+    let value = trim_ows(value);
+    if name.is_empty() || !name.bytes().all(is_tchar) {
+        Err(InvalidHead::InvalidHeaderName(name.to_owned()))
+    } else if is_framing_header(name) {
+        Err(InvalidHead::FramingHeader(name.to_owned()))
+    } else if !value.iter().copied().all(is_field_vchar) {
+        Err(InvalidHead::InvalidHeaderValue(name.to_owned()))
+    } else {
+        Ok((name.to_owned(), value.to_owned()))
+    }
+}
+
+fn is_framing_header(name: &str) -> bool {
+    name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("transfer-encoding")
+}
@@ -0,0 +1,414 @@
+use std::{fmt, future::Future, io, net::SocketAddr, pin::Pin, sync::Arc, time::Duration};
+
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, ToSocketAddrs},
+};
+
+use super::{
+    body::BodyDecoder, client::expects_continue, request_head::check_header, Body, Framing,
+    InvalidHead, ParserLimits, RequestHead, RequestParser, Version, CRLF,
+};
+use crate::{headers::Header, headers::HeaderMap, ParseError, ParseErrorKind};
+
+/// The largest request body a [Server] accepts by default
+pub const DEFAULT_MAX_BODY_LEN: u64 = 1024 * 1024;
+
+/// How long [Server::run] waits after failing to accept a connection
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What [Handler::handle] returns
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = ServerResponse> + Send + 'a>>;
+
+/// Answers the requests received by a [Server], one at a time per connection.
+///
+/// It's implemented for functions that take a request head and its body, and
+/// return a future, like `|req, body| async move { ... }`.
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, req: RequestHead, body: Vec<u8>) -> HandlerFuture<'_>;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(RequestHead, Vec<u8>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ServerResponse> + Send + 'static,
+{
+    fn handle(&self, req: RequestHead, body: Vec<u8>) -> HandlerFuture<'_> {
+        Box::pin(self(req, body))
+    }
+}
+
+/// A response returned by a [Handler]. Like [RequestHead], its framing
+/// headers aren't set by hand: `content-length` is derived from the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerResponse {
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>,
+}
+
+/// Builds a [ServerResponse]. Errors are reported by
+/// [ServerResponseBuilder::build], so that calls can be chained.
+#[derive(Debug)]
+pub struct ServerResponseBuilder {
+    res: ServerResponse,
+    error: Option<InvalidResponse>,
+}
+
+/// Returned by [ServerResponseBuilder::build] when something wouldn't make it
+/// onto the wire intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidResponse {
+    /// Status codes have three digits, and 1xx are interim, see
+    /// https://httpwg.org/specs/rfc9110.html#status.codes
+    InvalidStatus(u16),
+    InvalidHeaderName(String),
+    /// The value contains CR, LF, NUL or other control characters
+    InvalidHeaderValue(String),
+    /// `content-length` and `transfer-encoding` are set from the body
+    FramingHeader(String),
+}
+
+impl fmt::Display for InvalidResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidStatus(status) => write!(f, "invalid status code {status}"),
+            Self::InvalidHeaderName(name) => write!(f, "invalid header name {name:?}"),
+            Self::InvalidHeaderValue(name) => write!(f, "invalid value for header {name:?}"),
+            Self::FramingHeader(name) => {
+                write!(f, "{name:?} is set from the body, not by hand")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidResponse {}
+
+impl ServerResponse {
+    /// Starts building a response with no headers and an empty body. Interim
+    /// (1xx) responses are the server's business, so the status must be
+    /// between 200 and 599.
+    pub fn builder(status: u16) -> ServerResponseBuilder {
+        ServerResponseBuilder {
+            res: ServerResponse {
+                status,
+                headers: Default::default(),
+                body: Default::default(),
+            },
+            error: (!(200..600).contains(&status))
+                .then_some(InvalidResponse::InvalidStatus(status)),
+        }
+    }
+
+    /// A plain text response whose body is the reason phrase, used when the
+    /// server rejects a request before it reaches the handler.
+    fn error(status: u16) -> Self {
+        ServerResponse {
+            status,
+            headers: vec![("content-type".into(), b"text/plain".to_vec())],
+            body: format!("{}\n", reason_phrase(status)).into_bytes(),
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the headers, not including `content-length`
+    pub fn headers(&self) -> HeaderMap<'_> {
+        self.headers
+            .iter()
+            .map(|(name, value)| Header { name, value })
+            .collect()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Serializes the response to a request of the given version. The body
+    /// is left out for `HEAD` requests, and `close` adds `connection: close`.
+    fn encode(&self, version: Version, head_only: bool, close: bool, out: &mut Vec<u8>) {
+        let status_line = format!(
+            "HTTP/1.1 {} {}{CRLF}",
+            self.status,
+            reason_phrase(self.status)
+        );
+        out.extend_from_slice(status_line.as_bytes());
+
+        let mut header = |name: &[u8], value: &[u8]| {
+            out.extend_from_slice(name);
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value);
+            out.extend_from_slice(CRLF.as_bytes());
+        };
+        for (name, value) in &self.headers {
+            header(name.as_bytes(), value);
+        }
+        // those never have a body, see
+        // https://httpwg.org/specs/rfc9110.html#field.content-length
+        let has_body = !matches!(self.status, 204 | 304);
+        if has_body {
+            header(b"content-length", self.body.len().to_string().as_bytes());
+        }
+        // unless the handler already said so
+        let headers = self.headers();
+        if close {
+            if !headers.has_connection_option("close") {
+                header(b"connection", b"close");
+            }
+        } else if version == Version::Http10 && !headers.has_connection_option("keep-alive") {
+            header(b"connection", b"keep-alive");
+        }
+        out.extend_from_slice(CRLF.as_bytes());
+
+        if has_body && !head_only {
+            out.extend_from_slice(&self.body);
+        }
+    }
+}
+
+impl ServerResponseBuilder {
+    /// Adds a header, with the same rules as [super::RequestHeadBuilder::header]
+    pub fn header(mut self, name: &str, value: impl AsRef<[u8]>) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        let error = match check_header(name, value.as_ref()) {
+            Ok(header) => {
+                self.res.headers.push(header);
+                return self;
+            }
+            Err(InvalidHead::InvalidHeaderName(name)) => InvalidResponse::InvalidHeaderName(name),
+            Err(InvalidHead::InvalidHeaderValue(name)) => InvalidResponse::InvalidHeaderValue(name),
+            Err(InvalidHead::FramingHeader(name)) => InvalidResponse::FramingHeader(name),
+            Err(e) => unreachable!("not a header error: {e}"),
+        };
+        self.error = Some(error);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.res.body = body.into();
+        self
+    }
+
+    pub fn build(self) -> Result<ServerResponse, InvalidResponse> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.res),
+        }
+    }
+}
+
+/// A minimal HTTP/1.1 server: it accepts TCP connections, and serves each of
+/// them on its own task, passing requests to a [Handler].
+///
+/// Connections are kept alive unless the client (or the handler) asks
+/// otherwise. Request bodies are read fully before the handler is called,
+/// and responses are always sent with `content-length`.
+pub struct Server<H> {
+    listener: TcpListener,
+    handler: Arc<H>,
+    limits: ParserLimits,
+    max_body_len: u64,
+}
+
+impl<H> Server<H>
+where
+    H: Handler,
+{
+    /// Listens on the given address. Use port 0 to let the OS pick a free
+    /// port, see [Server::local_addr].
+    pub async fn bind(addr: impl ToSocketAddrs, handler: H) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            handler: Arc::new(handler),
+            limits: Default::default(),
+            max_body_len: DEFAULT_MAX_BODY_LEN,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Uses the given limits when parsing request heads. Requests that exceed
+    /// them get a `431 Request Header Fields Too Large`.
+    pub fn set_limits(&mut self, limits: ParserLimits) {
+        self.limits = limits;
+    }
+
+    /// Sets the largest request body accepted, see [DEFAULT_MAX_BODY_LEN].
+    /// Larger bodies get a `413 Payload Too Large`.
+    pub fn set_max_body_len(&mut self, max_body_len: u64) {
+        self.max_body_len = max_body_len;
+    }
+
+    /// Accepts connections forever. Failing to accept one isn't fatal: it's
+    /// logged, and the server tries again a little later.
+    pub async fn run(self) {
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(conn) => conn,
+                // usually out of file descriptors (EMFILE), or a connection
+                // reset before we got to it (ECONNABORTED). Retrying right
+                // away would spin while the former lasts.
+                Err(e) => {
+                    tracing::warn!("accepting a connection failed: {e}");
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let handler = self.handler.clone();
+            let (limits, max_body_len) = (self.limits, self.max_body_len);
+            tokio::spawn(async move {
+                if let Err(e) = serve_connection(stream, &*handler, &limits, max_body_len).await {
+                    tracing::debug!(%peer, "connection failed: {e}");
+                }
+            });
+        }
+    }
+}
+
+/// Serves requests on a single connection, until either side closes it
+async fn serve_connection<S, H>(
+    mut stream: S,
+    handler: &H,
+    limits: &ParserLimits,
+    max_body_len: u64,
+) -> color_eyre::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handler,
+{
+    let mut buf = BytesMut::new();
+    let mut parser = RequestParser::with_limits(*limits);
+    loop {
+        let req = match read_head(&mut stream, &mut buf, &mut parser).await? {
+            Some(Ok(req)) => req,
+            // the client closed the connection between requests
+            None => return Ok(()),
+            Some(Err(e)) => {
+                let status = match e.kind {
+                    ParseErrorKind::HeaderTooLarge
+                    | ParseErrorKind::TooManyHeaders
+                    | ParseErrorKind::HeadTooLarge => 431,
+                    _ => 400,
+                };
+                reject(&mut stream, Version::Http11, status).await?;
+                return Err(e.into());
+            }
+        };
+        let version = req.version();
+
+        // HTTP/1.1 requires a host header, see
+        // https://httpwg.org/specs/rfc9112.html#request.target
+        if version == Version::Http11 && !req.headers().contains("host") {
+            return reject(&mut stream, version, 400).await;
+        }
+        if matches!(req.framing(), Framing::ContentLength(len) if len > max_body_len) {
+            return reject(&mut stream, version, 413).await;
+        }
+
+        let mut decoder = BodyDecoder::new(req.framing());
+        if !decoder.is_done() && version == Version::Http11 && expects_continue(&req) {
+            stream
+                .write_all(format!("HTTP/1.1 100 Continue{CRLF}{CRLF}").as_bytes())
+                .await?;
+            stream.flush().await?;
+        }
+
+        let mut body = Vec::new();
+        let reader = Body {
+            stream: &mut stream,
+            buf: &mut buf,
+            decoder: &mut decoder,
+            finished: None,
+        };
+        // chunked bodies don't say how long they are upfront
+        reader
+            .take(max_body_len.saturating_add(1))
+            .read_to_end(&mut body)
+            .await?;
+        if body.len() as u64 > max_body_len {
+            return reject(&mut stream, version, 413).await;
+        }
+
+        let keep_alive = match version {
+            Version::Http10 => req.headers().has_connection_option("keep-alive"),
+            Version::Http11 => !req.headers().has_connection_option("close"),
+        };
+        let head_only = req.method() == "HEAD";
+        let res = handler.handle(req, body).await;
+        let close = !keep_alive || res.headers().has_connection_option("close");
+
+        let mut out = Vec::new();
+        res.encode(version, head_only, close, &mut out);
+        stream.write_all(&out).await?;
+        stream.flush().await?;
+        if close {
+            stream.shutdown().await?;
+            return Ok(());
+        }
+    }
+}
+
+/// Reads a request head, leaving anything after it in `buf`. Returns `None`
+/// if the stream ends before the head starts, and parse errors separately
+/// from I/O errors, since those are answered with an error response.
+async fn read_head<S>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    parser: &mut RequestParser,
+) -> io::Result<Option<Result<RequestHead, ParseError>>>
+where
+    S: AsyncRead + Unpin,
+{
+    loop {
+        // picks up where the last read left off, so that a head trickling in
+        // isn't parsed from the start every time
+        let res = parser.parse(buf).and_then(|(rest, req)| {
+            Ok((buf.len() - rest.len(), RequestHead::from_request(buf, req)?))
+        });
+        match res {
+            Ok((head_len, req)) => {
+                let _ = buf.split_to(head_len);
+                return Ok(Some(Ok(req)));
+            }
+            Err(nom::Err::Incomplete(_)) => {}
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => return Ok(Some(Err(e))),
+        }
+
+        if stream.read_buf(buf).await? == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "client closed connection during headers",
+            ));
+        }
+    }
+}
+
+/// Answers with an error response, and closes the connection
+async fn reject<S>(stream: &mut S, version: Version, status: u16) -> color_eyre::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let mut out = Vec::new();
+    ServerResponse::error(status).encode(version, false, true, &mut out);
+    stream.write_all(&out).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    http::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Unknown")
+}
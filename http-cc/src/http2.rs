@@ -6,16 +6,29 @@ use std::{
 use enum_repr::EnumRepr;
 use enumflags2::{bitflags, BitFlags};
 use nom::{
-    combinator::map_res,
     number::streaming::{be_u24, be_u8},
     sequence::tuple,
     IResult,
 };
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
+use crate::{
+    error::{finish, RawError, RawResult},
+    ParseError, ParseErrorKind,
+};
+
+mod convert;
+
+mod client;
+pub use client::ClientConnection;
+
 /// This is sent by h2 clients after negotiating over ALPN, or when doing h2c.
 pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
+/// The largest frame payload a peer may send before we advertise a larger
+/// SETTINGS_MAX_FRAME_SIZE, see https://httpwg.org/specs/rfc9113.html#SettingValues
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16_384;
+
 /// See https://httpwg.org/specs/rfc9113.html#FrameTypes
 #[EnumRepr(type = "u8")]
 #[derive(Debug)]
@@ -40,10 +53,56 @@ pub enum FrameType {
     RstStream,
     Settings(BitFlags<SettingsFlags>),
     PushPromise,
-    Ping,
+    Ping(BitFlags<PingFlags>),
     GoAway,
     WindowUpdate,
-    Continuation,
+    Continuation(BitFlags<ContinuationFlags>),
+    /// A frame type we don't know about, like ALTSVC or a GREASE value,
+    /// which must be ignored, see https://httpwg.org/specs/rfc9113.html#rfc.section.5.5
+    /// Its flags are dropped, since they mean nothing to us either.
+    Unknown(u8),
+}
+
+/// Sent in RST_STREAM and GOAWAY frames, see
+/// https://httpwg.org/specs/rfc9113.html#ErrorCodes
+#[EnumRepr(type = "u32")]
+#[derive(Debug)]
+pub enum ErrorCode {
+    NoError = 0x0,
+    ProtocolError = 0x1,
+    InternalError = 0x2,
+    FlowControlError = 0x3,
+    SettingsTimeout = 0x4,
+    StreamClosed = 0x5,
+    FrameSizeError = 0x6,
+    RefusedStream = 0x7,
+    Cancel = 0x8,
+    CompressionError = 0x9,
+    ConnectError = 0xa,
+    EnhanceYourCalm = 0xb,
+    InadequateSecurity = 0xc,
+    Http11Required = 0xd,
+}
+
+impl From<ParseErrorKind> for ErrorCode {
+    fn from(kind: ParseErrorKind) -> Self {
+        match kind {
+            ParseErrorKind::FrameTooLarge { .. } => ErrorCode::FrameSizeError,
+            _ => ErrorCode::ProtocolError,
+        }
+    }
+}
+
+/// See https://httpwg.org/specs/rfc9113.html#SettingValues
+#[EnumRepr(type = "u16")]
+#[derive(Debug)]
+pub enum Setting {
+    HeaderTableSize = 0x1,
+    EnablePush = 0x2,
+    MaxConcurrentStreams = 0x3,
+    InitialWindowSize = 0x4,
+    MaxFrameSize = 0x5,
+    MaxHeaderListSize = 0x6,
 }
 
 /// See https://httpwg.org/specs/rfc9113.html#SETTINGS
@@ -83,6 +142,22 @@ pub enum DataFlags {
     EndStream = 0x01,
 }
 
+/// See https://httpwg.org/specs/rfc9113.html#PING
+#[bitflags]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PingFlags {
+    Ack = 0x01,
+}
+
+/// See https://httpwg.org/specs/rfc9113.html#CONTINUATION
+#[bitflags]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContinuationFlags {
+    EndHeaders = 0x04,
+}
+
 /// This is just used to avoid dumping the entire payload in the [fmt::Debug]
 /// implementation of [Frame].
 #[derive(Default)]
@@ -110,6 +185,42 @@ impl fmt::Debug for OpaquePayload {
     }
 }
 
+/// A decoded header block, pseudo-headers (`:status`, `:path`, etc.) first.
+/// This is what [hpack::Decoder::decode] returns, and it can be converted to
+/// and from [http::Request] and [http::Response].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HeaderBlock(pub Vec<(Vec<u8>, Vec<u8>)>);
+
+impl HeaderBlock {
+    /// Returns the value of the given header or pseudo-header. Names are
+    /// always lowercase in HTTP/2.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == name.as_bytes())
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Returns the values of all headers with the given name, in order
+    pub fn get_all<'b>(&'b self, name: &'b str) -> impl Iterator<Item = &'b [u8]> + 'b {
+        self.0
+            .iter()
+            .filter(move |(k, _)| k == name.as_bytes())
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Appends a header (or pseudo-header)
+    pub fn push(&mut self, name: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        self.0.push((name.into(), value.into()))
+    }
+
+    /// Encodes the header block, so it can be used as the payload of a
+    /// HEADERS frame.
+    pub fn encode(&self, encoder: &mut hpack::Encoder<'_>) -> Vec<u8> {
+        encoder.encode(self.0.iter().map(|(k, v)| (&k[..], &v[..])))
+    }
+}
+
 impl Frame {
     /// Create a new frame with the given type and stream ID.
     pub fn new(frame_type: FrameType, stream_id: u32) -> Self {
@@ -124,19 +235,62 @@ impl Frame {
     /// Parse a frame from the given slice. This also takes the payload from the
     /// slice, and copies it to the heap, which may not be ideal for a production
     /// implementation.
-    pub fn parse(i: &[u8]) -> IResult<&[u8], Self> {
-        let (i, (length, frame_type, flags, (reserved, stream_id))) = tuple((
-            be_u24,
-            map_res(be_u8, |u| {
-                RawFrameType::from_repr(u).ok_or(nom::error::ErrorKind::OneOf)
-            }),
-            be_u8,
-            parse_reserved_and_stream_id,
-        ))(i)?;
-        let (i, payload) = nom::bytes::streaming::take(length)(i)?;
+    ///
+    /// Frames larger than [DEFAULT_MAX_FRAME_SIZE] are rejected, see
+    /// [Frame::parse_with_max_size].
+    pub fn parse(i: &[u8]) -> IResult<&[u8], Self, ParseError> {
+        Self::parse_with_max_size(i, DEFAULT_MAX_FRAME_SIZE)
+    }
 
-        //                  👇 new!
-        let frame_type = FrameType::decode(frame_type, flags);
+    /// Like [Frame::parse], for when we've advertised a different
+    /// SETTINGS_MAX_FRAME_SIZE. The frame is rejected as soon as its header
+    /// is read, without waiting for the payload.
+    pub fn parse_with_max_size(i: &[u8], max_size: u32) -> IResult<&[u8], Self, ParseError> {
+        finish(
+            i,
+            Self::raw_parse(i, max_size, false),
+            ParseErrorKind::InvalidFrame,
+        )
+    }
+
+    /// Like [Frame::parse], but frame types we don't know about are rejected
+    /// with [ParseErrorKind::UnknownFrameType] instead of being returned as
+    /// [FrameType::Unknown]. Peers are allowed to send those, so this is only
+    /// useful to test an implementation.
+    pub fn parse_strict(i: &[u8]) -> IResult<&[u8], Self, ParseError> {
+        finish(
+            i,
+            Self::raw_parse(i, DEFAULT_MAX_FRAME_SIZE, true),
+            ParseErrorKind::InvalidFrame,
+        )
+    }
+
+    fn raw_parse(i: &[u8], max_size: u32, strict: bool) -> RawResult<'_, Self> {
+        let start = i;
+        let (i, (length, frame_type, flags, (reserved, stream_id))) =
+            tuple((be_u24, be_u8, be_u8, parse_reserved_and_stream_id))(i)?;
+
+        if length > max_size {
+            return Err(nom::Err::Failure(RawError::new(
+                start,
+                ParseErrorKind::FrameTooLarge {
+                    len: length,
+                    max: max_size,
+                },
+            )));
+        }
+        let frame_type = match RawFrameType::from_repr(frame_type) {
+            Some(ty) => FrameType::decode(ty, flags),
+            None if strict => {
+                return Err(nom::Err::Failure(RawError::new(
+                    &start[3..],
+                    ParseErrorKind::UnknownFrameType(frame_type),
+                )))
+            }
+            None => FrameType::Unknown(frame_type),
+        };
+        // unknown frames still have a payload, which must be skipped
+        let (i, payload) = nom::bytes::streaming::take(length)(i)?;
 
         let frame = Frame {
             frame_type,
@@ -147,15 +301,52 @@ impl Frame {
         Ok((i, frame))
     }
 
+    /// Builds a GOAWAY frame, telling the peer we're done with the connection.
+    /// See https://httpwg.org/specs/rfc9113.html#GOAWAY
+    pub fn go_away(last_stream_id: u32, error_code: ErrorCode) -> Self {
+        let mut frame = Frame::new(FrameType::GoAway, 0);
+        frame
+            .payload
+            .extend_from_slice(&last_stream_id.to_be_bytes());
+        frame
+            .payload
+            .extend_from_slice(&error_code.repr().to_be_bytes());
+        frame
+    }
+
+    /// Builds a SETTINGS frame (without the ACK flag).
+    /// See https://httpwg.org/specs/rfc9113.html#SETTINGS
+    pub fn settings(settings: &[(Setting, u32)]) -> Self {
+        let mut frame = Frame::new(FrameType::Settings(Default::default()), 0);
+        for (setting, value) in settings {
+            frame
+                .payload
+                .extend_from_slice(&setting.repr().to_be_bytes());
+            frame.payload.extend_from_slice(&value.to_be_bytes());
+        }
+        frame
+    }
+
+    /// Builds a WINDOW_UPDATE frame, letting the peer send `increment` more
+    /// bytes of DATA. See https://httpwg.org/specs/rfc9113.html#WINDOW_UPDATE
+    pub fn window_update(stream_id: u32, increment: u32) -> Self {
+        let mut frame = Frame::new(FrameType::WindowUpdate, stream_id);
+        frame.payload.extend_from_slice(&increment.to_be_bytes());
+        frame
+    }
+
     /// Writes a frame to an [AsyncWrite].
-    pub async fn write(&self, w: &mut (dyn AsyncWrite + Unpin)) -> color_eyre::Result<()> {
+    pub async fn write<W>(&self, w: &mut W) -> color_eyre::Result<()>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
         let mut header = [0u8; 9];
         {
             use byteorder::{BigEndian, WriteBytesExt};
             let mut header = &mut header[..];
             header.write_u24::<BigEndian>(self.payload.len() as _)?;
             let (ty, flags) = self.frame_type.encode();
-            header.write_u8(ty.repr())?;
+            header.write_u8(ty)?;
             header.write_u8(flags)?;
             header.write_u32::<BigEndian>(self.stream_id)?;
         }
@@ -171,7 +362,7 @@ impl Frame {
 
 /// See https://httpwg.org/specs/rfc9113.html#FrameHeader - the first bit
 /// is reserved, and the rest is a 32-bit stream id
-fn parse_reserved_and_stream_id(i: &[u8]) -> IResult<&[u8], (u8, u32)> {
+fn parse_reserved_and_stream_id(i: &[u8]) -> RawResult<'_, (u8, u32)> {
     fn reserved(i: (&[u8], usize)) -> IResult<(&[u8], usize), u8> {
         nom::bits::streaming::take(1_usize)(i)
     }
@@ -184,19 +375,22 @@ fn parse_reserved_and_stream_id(i: &[u8]) -> IResult<&[u8], (u8, u32)> {
 }
 
 impl FrameType {
-    fn encode(&self) -> (RawFrameType, u8) {
-        match self {
+    /// Returns the raw frame type and flags
+    fn encode(&self) -> (u8, u8) {
+        let (ty, flags) = match self {
             FrameType::Data(f) => (RawFrameType::Data, f.bits()),
             FrameType::Headers(f) => (RawFrameType::Headers, f.bits()),
             FrameType::Priority => (RawFrameType::Priority, 0),
             FrameType::RstStream => (RawFrameType::RstStream, 0),
             FrameType::Settings(f) => (RawFrameType::Settings, f.bits()),
             FrameType::PushPromise => (RawFrameType::PushPromise, 0),
-            FrameType::Ping => (RawFrameType::Ping, 0),
+            FrameType::Ping(f) => (RawFrameType::Ping, f.bits()),
             FrameType::GoAway => (RawFrameType::GoAway, 0),
             FrameType::WindowUpdate => (RawFrameType::WindowUpdate, 0),
-            FrameType::Continuation => (RawFrameType::Continuation, 0),
-        }
+            FrameType::Continuation(f) => (RawFrameType::Continuation, f.bits()),
+            FrameType::Unknown(ty) => return (*ty, 0),
+        };
+        (ty.repr(), flags)
     }
 
     fn decode(ty: RawFrameType, flags: u8) -> Self {
@@ -211,10 +405,12 @@ impl FrameType {
                 FrameType::Settings(BitFlags::<SettingsFlags>::from_bits_truncate(flags))
             }
             RawFrameType::PushPromise => FrameType::PushPromise,
-            RawFrameType::Ping => FrameType::Ping,
+            RawFrameType::Ping => FrameType::Ping(BitFlags::<PingFlags>::from_bits_truncate(flags)),
             RawFrameType::GoAway => FrameType::GoAway,
             RawFrameType::WindowUpdate => FrameType::WindowUpdate,
-            RawFrameType::Continuation => FrameType::Continuation,
+            RawFrameType::Continuation => {
+                FrameType::Continuation(BitFlags::<ContinuationFlags>::from_bits_truncate(flags))
+            }
         }
     }
 }
@@ -0,0 +1,441 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL, Engine};
+use bytes::{Buf, BytesMut};
+use color_eyre::eyre::eyre;
+use hpack::encoder::encode_integer_into;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
+
+use super::{
+    ContinuationFlags, DataFlags, ErrorCode, Frame, FrameType, HeaderBlock, HeadersFlags,
+    PingFlags, Setting, SettingsFlags, PREFACE,
+};
+use crate::{
+    http1::{self, RequestHead},
+    timings::RequestTimings,
+};
+
+/// The settings we send in our connection preface. Server push is disabled,
+/// since we wouldn't know what to do with pushed responses.
+const SETTINGS: &[(Setting, u32)] = &[(Setting::EnablePush, 0)];
+
+/// The size of the HPACK dynamic tables until the peer says otherwise, see
+/// https://httpwg.org/specs/rfc9113.html#SettingValues
+const DEFAULT_HEADER_TABLE_SIZE: u32 = 4096;
+
+/// A minimal HTTP/2 client connection: it sends one request at a time, and
+/// reads its whole response, body included.
+///
+/// It works over TLS (once ALPN picked `h2`), and over cleartext TCP ("h2c"),
+/// either with prior knowledge or after an HTTP/1.1 upgrade.
+pub struct ClientConnection<S> {
+    stream: S,
+    /// Bytes read from the stream but not parsed yet
+    buf: BytesMut,
+    encoder: HeaderEncoder,
+    decoder: hpack::Decoder<'static>,
+    next_stream_id: u32,
+
+    /// How long it took to set up the connection, until a request reports it
+    setup: RequestTimings,
+    /// Timings of the last request
+    timings: RequestTimings,
+}
+
+impl<S> ClientConnection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Sends the connection preface, for when we know the server speaks
+    /// HTTP/2 already: because ALPN said so, or by prior knowledge for h2c.
+    /// See https://httpwg.org/specs/rfc9113.html#preface
+    pub async fn handshake(stream: S) -> color_eyre::Result<Self> {
+        Self::start(stream, Default::default(), 1).await
+    }
+
+    /// Asks an HTTP/1.1 server to switch to h2c, with a `GET` request for
+    /// `path`. If it agrees, the response to that request arrives over
+    /// HTTP/2 (on stream 1), and is returned along with the connection.
+    ///
+    /// This mechanism was deprecated by RFC 9113, but some servers still
+    /// support it. See https://www.rfc-editor.org/rfc/rfc7540#section-3.2
+    pub async fn upgrade(
+        stream: S,
+        host: &str,
+        path: &str,
+    ) -> color_eyre::Result<(Self, http::Response<Vec<u8>>)> {
+        let settings = Frame::settings(SETTINGS);
+        let req = RequestHead::builder("GET", path)
+            .header("host", host)
+            .header("connection", "upgrade, http2-settings")
+            .header("upgrade", "h2c")
+            .header("http2-settings", BASE64URL.encode(&settings.payload[..]))
+            .build()?;
+
+        let started = Instant::now();
+        let mut conn = http1::ClientConnection::new(stream);
+        {
+            let (res, _) = conn.send(&req, &[]).await?;
+            let upgraded = res
+                .headers
+                .get_list("upgrade")
+                .any(|v| v.eq_ignore_ascii_case(b"h2c"));
+            if res.status != 101 || !upgraded {
+                return Err(eyre!("server didn't switch to h2c (status {})", res.status));
+            }
+        }
+
+        // the server may have sent frames right after its response. the
+        // upgrade request implicitly used stream 1.
+        // the response started with the 101, but its final head is still to
+        // come
+        let mut timings = conn.timings();
+        timings.response_head = None;
+        let (stream, leftover) = conn.into_inner();
+        let mut conn = Self::start(stream, leftover, 3).await?;
+        let res = conn.read_response(1, started, &mut timings).await?;
+        conn.timings = timings;
+        Ok((conn, res))
+    }
+
+    async fn start(stream: S, buf: BytesMut, next_stream_id: u32) -> color_eyre::Result<Self> {
+        let mut conn = Self {
+            stream,
+            buf,
+            encoder: HeaderEncoder::Indexing(hpack::Encoder::new()),
+            decoder: hpack::Decoder::new(),
+            next_stream_id,
+            setup: Default::default(),
+            timings: Default::default(),
+        };
+        conn.stream.write_all(PREFACE).await?;
+        conn.write_frame(&Frame::settings(SETTINGS)).await?;
+        Ok(conn)
+    }
+
+    /// Hands over how long it took to set up the connection, so that it's
+    /// reported by the [Self::timings] of the next request. Only the setup
+    /// phases are kept, see [RequestTimings::setup].
+    pub fn set_connect_timings(&mut self, timings: RequestTimings) {
+        self.setup = timings.setup();
+    }
+
+    /// How long each phase of the last request took, including the one
+    /// made by [Self::upgrade]
+    pub fn timings(&self) -> RequestTimings {
+        self.timings
+    }
+
+    /// Sends a request without a body, and reads its response. Interim (1xx)
+    /// responses and trailers are skipped.
+    pub async fn send<B>(
+        &mut self,
+        req: &http::Request<B>,
+    ) -> color_eyre::Result<http::Response<Vec<u8>>> {
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 2;
+
+        let mut frame = Frame::new(
+            FrameType::Headers(HeadersFlags::EndHeaders | HeadersFlags::EndStream),
+            stream_id,
+        );
+        frame.payload.0 = self.encoder.encode(&HeaderBlock::try_from(req)?);
+
+        let started = Instant::now();
+        let mut timings = std::mem::take(&mut self.setup);
+        self.write_frame(&frame).await?;
+        timings.request_send = Some(started.elapsed());
+
+        let res = self.read_response(stream_id, started, &mut timings).await;
+        self.timings = timings;
+        res
+    }
+
+    /// Reads frames until the response on the given stream is complete,
+    /// handling connection-level frames along the way.
+    ///
+    /// The response phases of `timings` are filled in as they're done,
+    /// relative to `started`. The time to first byte is kept if it's already
+    /// known.
+    async fn read_response(
+        &mut self,
+        stream_id: u32,
+        started: Instant,
+        timings: &mut RequestTimings,
+    ) -> color_eyre::Result<http::Response<Vec<u8>>> {
+        let mut head_read = None;
+        let mut res = None;
+        let mut body = Vec::new();
+        // a header block, which may span HEADERS and CONTINUATION frames
+        let mut block = Vec::new();
+        let mut block_ends_stream = false;
+        // the stream of the header block being read, if it isn't over yet
+        let mut block_stream = None;
+
+        loop {
+            let frame = self.read_frame().await?;
+
+            // CONTINUATION frames must come right after the HEADERS frame
+            // they continue, on the same stream, with nothing in between.
+            // See https://httpwg.org/specs/rfc9113.html#CONTINUATION
+            let continuation = matches!(frame.frame_type, FrameType::Continuation(_));
+            match block_stream {
+                None if continuation => {
+                    let err = self.connection_error(ErrorCode::ProtocolError).await;
+                    return Err(err.wrap_err("unexpected CONTINUATION frame"));
+                }
+                Some(id) if !continuation || frame.stream_id != id => {
+                    let err = self.connection_error(ErrorCode::ProtocolError).await;
+                    return Err(err.wrap_err("header block interrupted by another frame"));
+                }
+                _ => {}
+            }
+
+            if frame.stream_id == stream_id && timings.time_to_first_byte.is_none() {
+                timings.time_to_first_byte = Some(started.elapsed());
+            }
+            let mut block_done = false;
+            match frame.frame_type {
+                FrameType::Settings(flags) => {
+                    if !flags.contains(SettingsFlags::Ack) {
+                        let Some(settings) = settings_fields(&frame.payload) else {
+                            let err = self.connection_error(ErrorCode::FrameSizeError).await;
+                            return Err(err.wrap_err("invalid SETTINGS frame"));
+                        };
+                        for (setting, value) in settings {
+                            if let Setting::HeaderTableSize = setting {
+                                self.encoder.set_table_size(value);
+                            }
+                        }
+                        let ack = Frame::new(FrameType::Settings(SettingsFlags::Ack.into()), 0);
+                        self.write_frame(&ack).await?;
+                    }
+                }
+                FrameType::Ping(flags) => {
+                    if !flags.contains(PingFlags::Ack) {
+                        let mut pong = Frame::new(FrameType::Ping(PingFlags::Ack.into()), 0);
+                        pong.payload.0 = frame.payload.0;
+                        self.write_frame(&pong).await?;
+                    }
+                }
+                FrameType::GoAway => {
+                    let (last_stream_id, code) = go_away_fields(&frame.payload)?;
+                    if last_stream_id < stream_id {
+                        return Err(eyre!("server went away ({code:?})"));
+                    }
+                }
+                FrameType::RstStream if frame.stream_id == stream_id => {
+                    let code = match frame.payload[..] {
+                        [a, b, c, d] => ErrorCode::from_repr(u32::from_be_bytes([a, b, c, d])),
+                        _ => None,
+                    };
+                    return Err(eyre!("server reset the stream ({code:?})"));
+                }
+                FrameType::Headers(flags) => {
+                    let fragment = unpad(
+                        &frame.payload,
+                        flags.contains(HeadersFlags::Padded),
+                        if flags.contains(HeadersFlags::Priority) {
+                            5
+                        } else {
+                            0
+                        },
+                    )?;
+                    block.extend_from_slice(fragment);
+                    block_ends_stream = flags.contains(HeadersFlags::EndStream);
+                    block_done = flags.contains(HeadersFlags::EndHeaders);
+                    block_stream = (!block_done).then_some(frame.stream_id);
+                }
+                FrameType::Continuation(flags) => {
+                    block.extend_from_slice(&frame.payload);
+                    block_done = flags.contains(ContinuationFlags::EndHeaders);
+                    block_stream = (!block_done).then_some(frame.stream_id);
+                }
+                FrameType::Data(flags) if frame.stream_id == stream_id => {
+                    body.extend_from_slice(unpad(
+                        &frame.payload,
+                        flags.contains(DataFlags::Padded),
+                        0,
+                    )?);
+
+                    // padding counts against flow control too
+                    if !frame.payload.is_empty() {
+                        let len = frame.payload.len() as u32;
+                        self.write_frame(&Frame::window_update(0, len)).await?;
+                        if !flags.contains(DataFlags::EndStream) {
+                            self.write_frame(&Frame::window_update(stream_id, len))
+                                .await?;
+                        }
+                    }
+                    if flags.contains(DataFlags::EndStream) {
+                        break;
+                    }
+                }
+                _ => {
+                    // ignore other types of frames, including unknown ones
+                    // (like ALTSVC), as required by
+                    // https://httpwg.org/specs/rfc9113.html#rfc.section.5.5
+                }
+            }
+
+            if block_done {
+                // header blocks must always be decoded, even for other
+                // streams, to keep the HPACK state in sync
+                let headers = HeaderBlock(
+                    self.decoder
+                        .decode(&block)
+                        .map_err(|e| eyre!("hpack error: {e:?}"))?,
+                );
+                block.clear();
+                if frame.stream_id != stream_id {
+                    continue;
+                }
+
+                let interim = headers
+                    .get(":status")
+                    .map(|status| status.starts_with(b"1"))
+                    .unwrap_or_default();
+                if res.is_none() && !interim {
+                    res = Some(http::Response::<()>::try_from(&headers)?);
+                    let first_byte = started + timings.time_to_first_byte.unwrap_or_default();
+                    let now = Instant::now();
+                    timings.response_head = Some(now - first_byte);
+                    head_read = Some(now);
+                }
+                if block_ends_stream {
+                    break;
+                }
+            }
+        }
+
+        let res = res.ok_or_else(|| eyre!("stream ended without a response"))?;
+        timings.response_body = head_read.map(|head_read| head_read.elapsed());
+        let (parts, ()) = res.into_parts();
+        Ok(http::Response::from_parts(parts, body))
+    }
+
+    async fn read_frame(&mut self) -> color_eyre::Result<Frame> {
+        loop {
+            match Frame::parse(&self.buf) {
+                Ok((rest, frame)) => {
+                    let consumed = self.buf.len() - rest.len();
+                    self.buf.advance(consumed);
+                    return Ok(frame);
+                }
+                Err(nom::Err::Incomplete(_)) => {}
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                    // let the server know why we're hanging up
+                    let go_away = Frame::go_away(0, ErrorCode::from(e.kind));
+                    let _ = self.write_frame(&go_away).await;
+                    return Err(e.into());
+                }
+            }
+
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(eyre!("connection closed"));
+            }
+        }
+    }
+
+    /// Hangs up on a server that broke the protocol, letting it know why
+    /// with a GOAWAY. See https://httpwg.org/specs/rfc9113.html#ConnectionErrorHandler
+    async fn connection_error(&mut self, code: ErrorCode) -> color_eyre::Report {
+        let err = eyre!("connection error: {code:?}");
+        let _ = self.write_frame(&Frame::go_away(0, code)).await;
+        err
+    }
+
+    async fn write_frame(&mut self, frame: &Frame) -> color_eyre::Result<()> {
+        frame.write(&mut self.stream).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+/// Compresses our header blocks. [hpack::Encoder] always indexes headers in a
+/// dynamic table of the default size, and can't be told to use a smaller one,
+/// so if the server asks for that, headers are sent as literals instead.
+enum HeaderEncoder {
+    Indexing(hpack::Encoder<'static>),
+    /// The server's table got smaller, and it doesn't know yet that we
+    /// emptied it
+    Shrinking,
+    Literal,
+}
+
+impl HeaderEncoder {
+    /// Applies the server's SETTINGS_HEADER_TABLE_SIZE
+    fn set_table_size(&mut self, size: u32) {
+        if size < DEFAULT_HEADER_TABLE_SIZE && matches!(self, Self::Indexing(_)) {
+            *self = Self::Shrinking;
+        }
+    }
+
+    fn encode(&mut self, block: &HeaderBlock) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Self::Indexing(encoder) => return block.encode(encoder),
+            Self::Shrinking => {
+                // a dynamic table size update to 0, which evicts everything,
+                // see https://www.rfc-editor.org/rfc/rfc7541#section-6.3
+                out.push(0x20);
+                *self = Self::Literal;
+            }
+            Self::Literal => {}
+        }
+        for (name, value) in &block.0 {
+            // a literal header field without indexing, with a literal name,
+            // see https://www.rfc-editor.org/rfc/rfc7541#section-6.2.2
+            out.push(0);
+            for s in [name, value] {
+                encode_integer_into(s.len(), 7, 0, &mut out).unwrap();
+                out.extend_from_slice(s);
+            }
+        }
+        out
+    }
+}
+
+/// Returns the known settings of a SETTINGS frame, or `None` if its payload
+/// has the wrong length. See https://httpwg.org/specs/rfc9113.html#SETTINGS
+fn settings_fields(payload: &[u8]) -> Option<Vec<(Setting, u32)>> {
+    if payload.len() % 6 != 0 {
+        return None;
+    }
+    let settings = payload
+        .chunks_exact(6)
+        .filter_map(|field| {
+            let setting = Setting::from_repr(u16::from_be_bytes([field[0], field[1]]))?;
+            let value = u32::from_be_bytes([field[2], field[3], field[4], field[5]]);
+            Some((setting, value))
+        })
+        .collect();
+    Some(settings)
+}
+
+/// Returns the last stream ID and error code of a GOAWAY frame
+fn go_away_fields(payload: &[u8]) -> color_eyre::Result<(u32, Option<ErrorCode>)> {
+    match payload {
+        [a, b, c, d, e, f, g, h, ..] => Ok((
+            u32::from_be_bytes([*a, *b, *c, *d]) & 0x7fff_ffff,
+            ErrorCode::from_repr(u32::from_be_bytes([*e, *f, *g, *h])),
+        )),
+        _ => Err(eyre!("GOAWAY frame too short")),
+    }
+}
+
+/// Strips the padding from the payload of a DATA or HEADERS frame, along with
+/// `skip` bytes of fields that come before the actual data (the priority
+/// fields of a HEADERS frame). See https://httpwg.org/specs/rfc9113.html#DATA
+fn unpad(payload: &[u8], padded: bool, skip: usize) -> color_eyre::Result<&[u8]> {
+    let (pad_len, payload) = match (padded, payload) {
+        (false, payload) => (0, payload),
+        (true, [pad_len, payload @ ..]) => (*pad_len as usize, payload),
+        (true, []) => return Err(eyre!("missing pad length")),
+    };
+    if skip + pad_len > payload.len() {
+        return Err(eyre!("padding larger than payload"));
+    }
+    Ok(&payload[skip..payload.len() - pad_len])
+}
@@ -0,0 +1,124 @@
+use super::HeaderBlock;
+use crate::ConvertError;
+
+/// These only make sense for HTTP/1.1 connections, and must not be sent over
+/// HTTP/2, see https://httpwg.org/specs/rfc9113.html#ConnectionSpecific
+const CONNECTION_SPECIFIC: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+impl HeaderBlock {
+    /// Appends regular headers, lowercased (`http` does that for us), minus
+    /// connection-specific ones. The `host` header is left out as well, since
+    /// it's superseded by `:authority`.
+    fn extend_from_map(&mut self, map: &http::HeaderMap) {
+        for (name, value) in map {
+            if CONNECTION_SPECIFIC.contains(&name.as_str()) || name == http::header::HOST {
+                continue;
+            }
+            self.push(name.as_str(), value.as_bytes());
+        }
+    }
+
+    /// Returns all regular headers, as an [http::HeaderMap]
+    fn to_map(&self) -> Result<http::HeaderMap, ConvertError> {
+        let mut map = http::HeaderMap::with_capacity(self.0.len());
+        for (name, value) in self.0.iter().filter(|(k, _)| !k.starts_with(b":")) {
+            map.append(
+                http::header::HeaderName::from_bytes(name)?,
+                http::HeaderValue::from_bytes(value)?,
+            );
+        }
+        Ok(map)
+    }
+
+    fn pseudo(&self, name: &'static str) -> Result<&str, ConvertError> {
+        self.get(name)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .ok_or(ConvertError::InvalidPseudoHeader(name))
+    }
+}
+
+impl<B> TryFrom<&http::Request<B>> for HeaderBlock {
+    type Error = ConvertError;
+
+    /// `:scheme` defaults to `https`, and `:authority` comes from the URI, or
+    /// from the `host` header if the URI is relative.
+    fn try_from(req: &http::Request<B>) -> Result<Self, Self::Error> {
+        let uri = req.uri();
+        let authority = match uri.authority() {
+            Some(authority) => authority.as_str().as_bytes(),
+            None => req
+                .headers()
+                .get(http::header::HOST)
+                .ok_or(ConvertError::MissingAuthority)?
+                .as_bytes(),
+        };
+
+        let mut block = HeaderBlock::default();
+        block.push(":method", req.method().as_str());
+        block.push(":scheme", uri.scheme_str().unwrap_or("https"));
+        block.push(":authority", authority);
+        block.push(
+            ":path",
+            uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/"),
+        );
+        block.extend_from_map(req.headers());
+        Ok(block)
+    }
+}
+
+impl TryFrom<&HeaderBlock> for http::Request<()> {
+    type Error = ConvertError;
+
+    fn try_from(block: &HeaderBlock) -> Result<Self, Self::Error> {
+        let uri = http::Uri::builder()
+            .scheme(block.pseudo(":scheme")?)
+            .authority(block.pseudo(":authority")?)
+            .path_and_query(block.pseudo(":path")?)
+            .build()?;
+
+        let mut req = http::Request::builder()
+            .method(block.pseudo(":method")?)
+            .uri(uri)
+            .version(http::Version::HTTP_2)
+            .body(())?;
+        *req.headers_mut() = block.to_map()?;
+        Ok(req)
+    }
+}
+
+impl<B> TryFrom<&http::Response<B>> for HeaderBlock {
+    type Error = ConvertError;
+
+    /// HTTP/2 has no `101 Switching Protocols`, see
+    /// https://httpwg.org/specs/rfc9113.html#informational-responses
+    fn try_from(res: &http::Response<B>) -> Result<Self, Self::Error> {
+        if res.status() == http::StatusCode::SWITCHING_PROTOCOLS {
+            return Err(ConvertError::InvalidPseudoHeader(":status"));
+        }
+
+        let mut block = HeaderBlock::default();
+        block.push(":status", res.status().as_str());
+        block.extend_from_map(res.headers());
+        Ok(block)
+    }
+}
+
+impl TryFrom<&HeaderBlock> for http::Response<()> {
+    type Error = ConvertError;
+
+    fn try_from(block: &HeaderBlock) -> Result<Self, Self::Error> {
+        let status = block.pseudo(":status")?;
+        let mut res = http::Response::builder()
+            .status(status)
+            .version(http::Version::HTTP_2)
+            .body(())?;
+        *res.headers_mut() = block.to_map()?;
+        Ok(res)
+    }
+}
@@ -1,2 +1,13 @@
+pub mod connect;
+pub mod dns;
+pub mod encoding;
+pub mod headers;
 pub mod http1;
 pub mod http2;
+pub mod proxy;
+pub mod redirect;
+pub mod timings;
+pub mod websocket;
+
+mod error;
+pub use error::{ConvertError, ParseError, ParseErrorKind};
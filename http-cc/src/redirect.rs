@@ -0,0 +1,231 @@
+use std::fmt;
+
+use http::Uri;
+
+/// How many redirects [RedirectPolicy] follows by default, like browsers
+/// and curl's `--max-redirs` usually do.
+pub const DEFAULT_MAX_HOPS: usize = 10;
+
+/// Decides which redirects get followed, and what happens to the request
+/// along the way. See [RedirectPolicy::start].
+///
+/// It doesn't send anything itself, so it works the same for HTTP/1.1 and
+/// HTTP/2 clients: they pass each response's status and `location` to
+/// [Redirects::follow], and send the next request if asked to.
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+    max_hops: usize,
+    sensitive_headers: Vec<String>,
+}
+
+impl Default for RedirectPolicy {
+    /// Follows up to [DEFAULT_MAX_HOPS] redirects, and strips credentials
+    /// (`authorization`, `proxy-authorization` and `cookie`) when leaving the
+    /// original origin.
+    fn default() -> Self {
+        Self {
+            max_hops: DEFAULT_MAX_HOPS,
+            sensitive_headers: ["authorization", "proxy-authorization", "cookie"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Returned when a redirect can't (or shouldn't) be followed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectError {
+    /// The URL isn't absolute, or isn't `http` or `https`
+    InvalidUrl(String),
+    /// More than [RedirectPolicy::max_hops] redirects in a row
+    TooManyRedirects(usize),
+    /// This request was already sent earlier in the chain
+    Loop(String),
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUrl(url) => write!(f, "invalid redirect URL {url:?}"),
+            Self::TooManyRedirects(max) => write!(f, "more than {max} redirects"),
+            Self::Loop(url) => write!(f, "redirect loop at {url:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RedirectError {}
+
+impl RedirectPolicy {
+    /// Stops after `max_hops` redirects, zero meaning redirects aren't
+    /// followed at all.
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Adds a header that's only sent to the origin of the original request
+    pub fn sensitive_header(mut self, name: &str) -> Self {
+        self.sensitive_headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        self.sensitive_headers
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    /// Starts following redirects for a request. `url` must be absolute, so
+    /// that relative `location`s can be resolved.
+    pub fn start(&self, method: &str, url: Uri) -> Result<Redirects, RedirectError> {
+        let origin = origin(&url).ok_or_else(|| RedirectError::InvalidUrl(url.to_string()))?;
+        Ok(Redirects {
+            policy: self.clone(),
+            method: method.to_owned(),
+            url: url.clone(),
+            origin,
+            cross_origin: false,
+            keep_body: true,
+            visited: vec![(method.to_owned(), url)],
+        })
+    }
+}
+
+/// The state of a request going through redirects: [Redirects::method] and
+/// [Redirects::url] say what to send next.
+#[derive(Debug, Clone)]
+pub struct Redirects {
+    policy: RedirectPolicy,
+    method: String,
+    url: Uri,
+    /// Scheme, host and port of the original request
+    origin: (String, String, u16),
+    /// True once any request in the chain went to another origin
+    cross_origin: bool,
+    keep_body: bool,
+    /// Every request sent so far, including the current one
+    visited: Vec<(String, Uri)>,
+}
+
+impl Redirects {
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn url(&self) -> &Uri {
+        &self.url
+    }
+
+    /// How many redirects were followed so far
+    pub fn hops(&self) -> usize {
+        self.visited.len() - 1
+    }
+
+    /// False once the request was turned into a `GET`, after which the
+    /// original body (and its framing) must not be sent.
+    pub fn keep_body(&self) -> bool {
+        self.keep_body
+    }
+
+    /// False for headers that must be left out of the next request: the
+    /// sensitive ones, once the chain has left the original origin (even if
+    /// it comes back later).
+    pub fn keep_header(&self, name: &str) -> bool {
+        !(self.cross_origin && self.policy.is_sensitive(name))
+    }
+
+    /// Looks at the response to the current request. Returns true if it's a
+    /// redirect to follow, in which case [Redirects::method] and
+    /// [Redirects::url] now describe the next request, and false if it's the
+    /// response to hand to the caller.
+    ///
+    /// `location` is the raw header value, see [crate::http1::Response::location].
+    /// Like browsers, a `POST` redirected by 301 or 302 becomes a `GET`, and so
+    /// does anything redirected by 303 except `HEAD`. See
+    /// https://httpwg.org/specs/rfc9110.html#status.3xx
+    pub fn follow(&mut self, status: u16, location: Option<&str>) -> Result<bool, RedirectError> {
+        let location = match (status, location) {
+            (301 | 302 | 303 | 307 | 308, Some(location)) => location,
+            // other 3xx responses (and redirects without a location) are
+            // meant for the caller
+            _ => return Ok(false),
+        };
+        if self.hops() >= self.policy.max_hops {
+            return Err(RedirectError::TooManyRedirects(self.policy.max_hops));
+        }
+
+        let url = resolve(&self.url, location)
+            .ok_or_else(|| RedirectError::InvalidUrl(location.to_owned()))?;
+        let to_get = match status {
+            303 => self.method != "HEAD",
+            301 | 302 => self.method == "POST",
+            _ => false,
+        };
+        let method = if to_get { "GET" } else { &self.method }.to_owned();
+
+        let next = (method, url);
+        if self.visited.contains(&next) {
+            return Err(RedirectError::Loop(next.1.to_string()));
+        }
+        self.visited.push(next.clone());
+        (self.method, self.url) = next;
+        self.keep_body &= !to_get;
+        self.cross_origin |= origin(&self.url).as_ref() != Some(&self.origin);
+        Ok(true)
+    }
+}
+
+/// Returns the scheme, lowercase host and port of an `http` or `https` URL,
+/// see https://httpwg.org/specs/rfc9110.html#origin
+fn origin(url: &Uri) -> Option<(String, String, u16)> {
+    let scheme = url.scheme_str()?.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+    let host = url.host().filter(|h| !h.is_empty())?.to_ascii_lowercase();
+    let port = url.port_u16().unwrap_or(default_port);
+    Some((scheme, host, port))
+}
+
+/// Resolves a `location` against the URL of the request it answers. Fragments
+/// are dropped, and dot segments are left for the server to deal with. See
+/// https://httpwg.org/specs/rfc9110.html#field.location
+fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    let location = location.split('#').next().unwrap_or_default();
+    let scheme = base.scheme_str()?;
+    let authority = base.authority()?;
+
+    let url = if location.starts_with("//") {
+        format!("{scheme}:{location}")
+    } else if location.starts_with('/') {
+        format!("{scheme}://{authority}{location}")
+    } else if location.starts_with('?') {
+        format!("{scheme}://{authority}{}{location}", base.path())
+    } else if has_scheme(location) {
+        location.to_owned()
+    } else {
+        // relative to the "directory" of the base path
+        let path = base.path();
+        let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        format!("{scheme}://{authority}{dir}{location}")
+    };
+
+    let url: Uri = url.parse().ok()?;
+    origin(&url)?;
+    Some(url)
+}
+
+/// See https://www.rfc-editor.org/rfc/rfc3986#section-3.1
+fn has_scheme(s: &str) -> bool {
+    match s.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
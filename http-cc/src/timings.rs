@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use tracing::{field::Empty, Span};
+
+/// How long each phase of a request took, like the "timing" tab of browser
+/// devtools. Phases that didn't happen are `None`: there's no DNS lookup or
+/// TCP connect when a connection is reused, no TLS handshake for h2c, and no
+/// body timing until the body was read fully.
+///
+/// The client connections measure everything from sending the request on,
+/// see `ClientConnection::timings` in [crate::http1] and [crate::http2]. The
+/// connection setup phases are measured by whoever sets up the connection,
+/// and handed over with `ClientConnection::set_connect_timings`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestTimings {
+    /// Resolving the host name
+    pub dns_lookup: Option<Duration>,
+    /// Establishing the TCP connection, or the tunnel through a proxy
+    pub tcp_connect: Option<Duration>,
+    pub tls_handshake: Option<Duration>,
+    /// Writing the request, body included. When the request expects a `100
+    /// Continue`, this includes waiting for it, unless the server answered
+    /// with a final response right away, and the body was never sent.
+    pub request_send: Option<Duration>,
+    /// From the start of the request until the first byte of the response
+    /// arrived, so this includes [Self::request_send]. For HTTP/2, that's the
+    /// first frame of the response's stream. Interim responses count, except
+    /// for the `100 Continue` the body waited for.
+    pub time_to_first_byte: Option<Duration>,
+    /// From the first byte of the response until its (final) head was read
+    pub response_head: Option<Duration>,
+    /// From the end of the response head until the end of the body
+    pub response_body: Option<Duration>,
+}
+
+impl RequestTimings {
+    /// Only keeps the connection setup phases: DNS lookup, TCP connect and
+    /// TLS handshake
+    pub fn setup(&self) -> Self {
+        Self {
+            dns_lookup: self.dns_lookup,
+            tcp_connect: self.tcp_connect,
+            tls_handshake: self.tls_handshake,
+            ..Default::default()
+        }
+    }
+
+    /// The sum of every phase that happened
+    pub fn total(&self) -> Duration {
+        [
+            self.dns_lookup,
+            self.tcp_connect,
+            self.tls_handshake,
+            self.time_to_first_byte,
+            self.response_head,
+            self.response_body,
+        ]
+        .into_iter()
+        .flatten()
+        .sum()
+    }
+
+    /// Every phase with its name, in order, as used for span fields
+    pub fn phases(&self) -> [(&'static str, Option<Duration>); 7] {
+        [
+            ("dns_lookup_ms", self.dns_lookup),
+            ("tcp_connect_ms", self.tcp_connect),
+            ("tls_handshake_ms", self.tls_handshake),
+            ("request_send_ms", self.request_send),
+            ("time_to_first_byte_ms", self.time_to_first_byte),
+            ("response_head_ms", self.response_head),
+            ("response_body_ms", self.response_body),
+        ]
+    }
+
+    /// Creates an `info` span named `request`, which has a field for every
+    /// phase (and `total_ms`), to be filled in by [Self::record].
+    pub fn span() -> Span {
+        tracing::info_span!(
+            "request",
+            dns_lookup_ms = Empty,
+            tcp_connect_ms = Empty,
+            tls_handshake_ms = Empty,
+            request_send_ms = Empty,
+            time_to_first_byte_ms = Empty,
+            response_head_ms = Empty,
+            response_body_ms = Empty,
+            total_ms = Empty,
+        )
+    }
+
+    /// Records the phases that happened on `span`, as fractional
+    /// milliseconds. Spans only have the fields they were created with, so
+    /// this is meant for spans from [Self::span], or that declare the same
+    /// fields (see [Self::phases]). Other fields are ignored.
+    pub fn record(&self, span: &Span) {
+        for (name, duration) in self.phases() {
+            if let Some(duration) = duration {
+                span.record(name, millis(duration));
+            }
+        }
+        span.record("total_ms", millis(self.total()));
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bytes::{Buf, BytesMut};
+use color_eyre::eyre::eyre;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::http1::{ClientConnection, InvalidHead, RequestHead, Response};
+
+mod frame;
+pub use frame::{CloseCode, Frame, Opcode, DEFAULT_MAX_PAYLOAD_LEN};
+
+/// Appended to the client's key before hashing it, see
+/// https://www.rfc-editor.org/rfc/rfc6455#section-1.3
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Generates a random `sec-websocket-key`
+pub fn generate_key() -> String {
+    BASE64.encode(rand::random::<[u8; 16]>())
+}
+
+/// Computes the `sec-websocket-accept` a server must answer with, for a given
+/// `sec-websocket-key`.
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(GUID.as_bytes());
+    BASE64.encode(hasher.digest().bytes())
+}
+
+/// Builds the request that asks a server to switch to WebSocket, see
+/// https://www.rfc-editor.org/rfc/rfc6455#section-4.1
+pub fn handshake_request(host: &str, path: &str, key: &str) -> Result<RequestHead, InvalidHead> {
+    RequestHead::builder("GET", path)
+        .header("host", host)
+        .header("upgrade", "websocket")
+        .header("connection", "upgrade")
+        .header("sec-websocket-key", key)
+        .header("sec-websocket-version", "13")
+        .build()
+}
+
+/// Returned by [verify_handshake] when the server didn't agree to switch to
+/// WebSocket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// Anything other than `101 Switching Protocols`
+    UnexpectedStatus(u16),
+    /// The `upgrade` or `connection` header is missing or wrong
+    MissingUpgrade,
+    /// The `sec-websocket-accept` header doesn't match our key
+    InvalidAccept,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedStatus(status) => write!(f, "unexpected status {status}"),
+            Self::MissingUpgrade => write!(f, "missing upgrade to websocket"),
+            Self::InvalidAccept => write!(f, "invalid sec-websocket-accept"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+/// Checks the server's response to [handshake_request], see
+/// https://www.rfc-editor.org/rfc/rfc6455#section-4.1
+pub fn verify_handshake(res: &Response<'_>, key: &str) -> Result<(), HandshakeError> {
+    if res.status != 101 {
+        return Err(HandshakeError::UnexpectedStatus(res.status));
+    }
+    let upgrade = res
+        .headers
+        .get_list("upgrade")
+        .any(|v| v.eq_ignore_ascii_case(b"websocket"));
+    if !upgrade || !res.headers.has_connection_option("upgrade") {
+        return Err(HandshakeError::MissingUpgrade);
+    }
+    if res.headers.get("sec-websocket-accept") != Some(accept_key(key).as_str()) {
+        return Err(HandshakeError::InvalidAccept);
+    }
+    Ok(())
+}
+
+/// Performs the opening handshake over an HTTP/1.1 connection, and returns the
+/// resulting [WebSocket] client.
+pub async fn connect<S>(stream: S, host: &str, path: &str) -> color_eyre::Result<WebSocket<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let key = generate_key();
+    let req = handshake_request(host, path, &key)?;
+
+    let mut conn = ClientConnection::new(stream);
+    {
+        let (res, _) = conn.send(&req, &[]).await?;
+        verify_handshake(&res, &key)?;
+    }
+    // the server may have sent frames right after its response
+    let (stream, leftover) = conn.into_inner();
+    Ok(WebSocket::new(stream, leftover, Role::Client))
+}
+
+/// Which end of the connection we are: clients mask the frames they send,
+/// servers don't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// A complete message, reassembled from fragments if needed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The status code and reason, if any
+    Close(Option<(u16, String)>),
+}
+
+/// A WebSocket connection, after the opening handshake.
+///
+/// Pings are answered automatically (and still returned by
+/// [WebSocket::recv]), and so are close frames.
+pub struct WebSocket<S> {
+    stream: S,
+    role: Role,
+    /// Bytes read from the stream but not parsed yet
+    buf: BytesMut,
+    /// The opcode and data of a fragmented message being received
+    fragments: Option<(Opcode, Vec<u8>)>,
+    max_message_len: u32,
+    /// Outgoing messages larger than this are split into several frames
+    fragment_size: usize,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl<S> WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Wraps a stream on which the handshake was done already. `leftover` is
+    /// whatever was read past the end of the handshake.
+    pub fn new(stream: S, leftover: impl Into<BytesMut>, role: Role) -> Self {
+        Self {
+            stream,
+            role,
+            buf: leftover.into(),
+            fragments: None,
+            max_message_len: DEFAULT_MAX_PAYLOAD_LEN,
+            fragment_size: usize::MAX,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Sets the largest message (and frame) we accept, defaults to
+    /// [DEFAULT_MAX_PAYLOAD_LEN]. Larger ones close the connection with
+    /// [CloseCode::MessageTooBig].
+    pub fn set_max_message_len(&mut self, max_len: u32) {
+        self.max_message_len = max_len;
+    }
+
+    /// Makes [WebSocket::send] split text and binary messages into frames of
+    /// at most `size` bytes of payload. By default, they're never split.
+    pub fn set_fragment_size(&mut self, size: usize) {
+        self.fragment_size = size.max(1);
+    }
+
+    /// Sends a message. Sending a close message starts the closing handshake,
+    /// after which nothing else can be sent.
+    pub async fn send(&mut self, msg: Message) -> color_eyre::Result<()> {
+        if self.close_sent {
+            return Err(eyre!("close frame already sent"));
+        }
+
+        let (opcode, payload) = match msg {
+            Message::Text(text) => (Opcode::Text, text.into_bytes()),
+            Message::Binary(data) => (Opcode::Binary, data),
+            Message::Ping(data) => (Opcode::Ping, data),
+            Message::Pong(data) => (Opcode::Pong, data),
+            Message::Close(close) => {
+                let frame = match close {
+                    Some((code, _)) if !CloseCode::is_valid(code) => {
+                        return Err(eyre!("invalid close code {code}"));
+                    }
+                    Some((code, reason)) => Frame::close(code, &reason),
+                    None => Frame::new(Opcode::Close, []),
+                };
+                return self.send_close(frame).await;
+            }
+        };
+        if opcode.is_control() {
+            if payload.len() > 125 {
+                return Err(eyre!("control frame payload too large"));
+            }
+            return self.write_frame(Frame::new(opcode, payload)).await;
+        }
+
+        let mut out = Vec::with_capacity(payload.len() + 14);
+        let mut chunks = payload.chunks(self.fragment_size).peekable();
+        let mut frame_opcode = opcode;
+        // empty messages still need one frame
+        while let Some(chunk) = chunks.next().or(Some(&[])) {
+            let mut frame = Frame::new(frame_opcode, chunk);
+            frame.fin = chunks.peek().is_none();
+            self.mask(&mut frame);
+            frame.encode(&mut out);
+            if frame.fin {
+                break;
+            }
+            frame_opcode = Opcode::Continuation;
+        }
+        self.stream.write_all(&out).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Receives the next message. Returns `None` once the closing handshake is
+    /// done: the close message itself is returned first.
+    ///
+    /// Protocol errors close the connection with the appropriate
+    /// [CloseCode] before being returned.
+    pub async fn recv(&mut self) -> color_eyre::Result<Option<Message>> {
+        loop {
+            if self.close_received {
+                return Ok(None);
+            }
+
+            let frame = self.read_frame().await?;
+            let expect_masked = self.role == Role::Server;
+            if frame.mask.is_some() != expect_masked {
+                return Err(self.fail(CloseCode::ProtocolError, "bad masking").await);
+            }
+
+            match frame.opcode {
+                Opcode::Ping => {
+                    if !self.close_sent {
+                        self.write_frame(Frame::new(Opcode::Pong, frame.payload.clone()))
+                            .await?;
+                    }
+                    return Ok(Some(Message::Ping(frame.payload)));
+                }
+                Opcode::Pong => return Ok(Some(Message::Pong(frame.payload))),
+                Opcode::Close => {
+                    self.close_received = true;
+                    let close = match &frame.payload[..] {
+                        [] => None,
+                        [a, b, reason @ ..] => {
+                            let code = u16::from_be_bytes([*a, *b]);
+                            if !CloseCode::is_valid(code) {
+                                return Err(self
+                                    .fail(CloseCode::ProtocolError, "invalid close code")
+                                    .await);
+                            }
+                            match std::str::from_utf8(reason) {
+                                Ok(reason) => Some((code, reason.to_owned())),
+                                Err(_) => {
+                                    return Err(self
+                                        .fail(CloseCode::InvalidPayload, "invalid close reason")
+                                        .await)
+                                }
+                            }
+                        }
+                        [_] => return Err(self.fail(CloseCode::ProtocolError, "bad close").await),
+                    };
+                    if !self.close_sent {
+                        // echo the status code, as recommended
+                        let reply = match close {
+                            Some((code, _)) => Frame::close(code, ""),
+                            None => Frame::new(Opcode::Close, []),
+                        };
+                        self.send_close(reply).await?;
+                    }
+                    return Ok(Some(Message::Close(close)));
+                }
+                Opcode::Text | Opcode::Binary => {
+                    if self.fragments.is_some() {
+                        return Err(self
+                            .fail(CloseCode::ProtocolError, "expected continuation frame")
+                            .await);
+                    }
+                    if !frame.fin {
+                        self.fragments = Some((frame.opcode, frame.payload));
+                        continue;
+                    }
+                    return self.message(frame.opcode, frame.payload).await.map(Some);
+                }
+                Opcode::Continuation => {
+                    let Some((opcode, mut data)) = self.fragments.take() else {
+                        return Err(self
+                            .fail(CloseCode::ProtocolError, "unexpected continuation frame")
+                            .await);
+                    };
+                    if data.len() + frame.payload.len() > self.max_message_len as usize {
+                        return Err(self.fail(CloseCode::MessageTooBig, "message too big").await);
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if !frame.fin {
+                        self.fragments = Some((opcode, data));
+                        continue;
+                    }
+                    return self.message(opcode, data).await.map(Some);
+                }
+            }
+        }
+    }
+
+    /// Starts the closing handshake, and waits for the peer to finish it.
+    /// Messages received in the meantime are discarded. `code` must be one
+    /// that can be sent, see [CloseCode::is_valid].
+    pub async fn close(&mut self, code: u16, reason: &str) -> color_eyre::Result<()> {
+        if !CloseCode::is_valid(code) {
+            return Err(eyre!("invalid close code {code}"));
+        }
+        if !self.close_sent {
+            self.send_close(Frame::close(code, reason)).await?;
+        }
+        while self.recv().await?.is_some() {}
+        Ok(())
+    }
+
+    /// Returns the underlying stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Turns a complete data message into a [Message]
+    async fn message(&mut self, opcode: Opcode, data: Vec<u8>) -> color_eyre::Result<Message> {
+        if opcode == Opcode::Binary {
+            return Ok(Message::Binary(data));
+        }
+        match String::from_utf8(data) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => Err(self.fail(CloseCode::InvalidPayload, "invalid UTF-8").await),
+        }
+    }
+
+    async fn read_frame(&mut self) -> color_eyre::Result<Frame> {
+        loop {
+            match Frame::parse_with_max_len(&self.buf, self.max_message_len) {
+                Ok((rest, frame)) => {
+                    let consumed = self.buf.len() - rest.len();
+                    self.buf.advance(consumed);
+                    return Ok(frame);
+                }
+                Err(nom::Err::Incomplete(_)) => {}
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                    // let the peer know why we're hanging up
+                    let _ = self.fail(CloseCode::from(e.kind), "").await;
+                    return Err(e.into());
+                }
+            }
+
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(eyre!("connection closed without a close frame"));
+            }
+        }
+    }
+
+    async fn send_close(&mut self, frame: Frame) -> color_eyre::Result<()> {
+        self.close_sent = true;
+        self.write_frame(frame).await
+    }
+
+    /// Closes the connection because of a protocol error, and returns the
+    /// error to report.
+    async fn fail(&mut self, code: CloseCode, reason: &str) -> color_eyre::Report {
+        self.close_received = true;
+        if !self.close_sent {
+            // we're giving up on this connection anyway
+            let _ = self.send_close(Frame::close(code.repr(), reason)).await;
+        }
+        eyre!("websocket protocol error: {code:?} {reason}")
+    }
+
+    async fn write_frame(&mut self, mut frame: Frame) -> color_eyre::Result<()> {
+        self.mask(&mut frame);
+        let mut out = Vec::with_capacity(14 + frame.payload.len());
+        frame.encode(&mut out);
+        self.stream.write_all(&out).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Clients must mask every frame with a fresh key, see
+    /// https://www.rfc-editor.org/rfc/rfc6455#section-5.3
+    fn mask(&self, frame: &mut Frame) {
+        if self.role == Role::Client {
+            frame.mask = Some(rand::random());
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use enum_repr::EnumRepr;
+use nom::{
+    bytes::streaming::take,
+    number::streaming::{be_u16, be_u64, be_u8},
+    IResult,
+};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    error::{finish, RawError, RawResult},
+    ParseError, ParseErrorKind,
+};
+
+/// The largest payload accepted by [Frame::parse], see
+/// [Frame::parse_with_max_len]
+pub const DEFAULT_MAX_PAYLOAD_LEN: u32 = 16 * 1024 * 1024;
+
+/// See https://www.rfc-editor.org/rfc/rfc6455#section-5.2
+#[EnumRepr(type = "u8")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xa,
+}
+
+impl Opcode {
+    /// Control frames can't be fragmented, and can be sent in the middle of a
+    /// fragmented message, see https://www.rfc-editor.org/rfc/rfc6455#section-5.5
+    pub fn is_control(self) -> bool {
+        self.repr() & 0x8 != 0
+    }
+}
+
+/// Sent in close frames, see https://www.rfc-editor.org/rfc/rfc6455#section-7.4.1
+///
+/// Applications may use codes from 3000 to 4999 too, which is why close
+/// frames carry a plain `u16`.
+#[EnumRepr(type = "u16")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCode {
+    Normal = 1000,
+    GoingAway = 1001,
+    ProtocolError = 1002,
+    UnsupportedData = 1003,
+    InvalidPayload = 1007,
+    PolicyViolation = 1008,
+    MessageTooBig = 1009,
+    MandatoryExtension = 1010,
+    InternalError = 1011,
+}
+
+impl CloseCode {
+    /// Returns true if `code` may be sent in a close frame. Some codes only
+    /// exist to be reported locally (1005 for "no code", 1006 for "closed
+    /// without a close frame", 1015 for TLS failures), and others are
+    /// reserved, see https://www.rfc-editor.org/rfc/rfc6455#section-7.4
+    ///
+    /// 1012 to 1014 were registered after the RFC, see
+    /// https://www.iana.org/assignments/websocket/websocket.xhtml#close-code-number
+    pub fn is_valid(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+impl From<ParseErrorKind> for CloseCode {
+    fn from(kind: ParseErrorKind) -> Self {
+        match kind {
+            ParseErrorKind::FrameTooLarge { .. } => CloseCode::MessageTooBig,
+            _ => CloseCode::ProtocolError,
+        }
+    }
+}
+
+/// See https://www.rfc-editor.org/rfc/rfc6455#section-5.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// False for all but the last frame of a fragmented message
+    pub fin: bool,
+    pub opcode: Opcode,
+    /// Clients must mask every frame they send, servers must not. The payload
+    /// is always stored unmasked: masking happens in [Frame::encode], and
+    /// unmasking in [Frame::parse].
+    pub mask: Option<[u8; 4]>,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates an unmasked, unfragmented frame
+    pub fn new(opcode: Opcode, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            mask: None,
+            payload: payload.into(),
+        }
+    }
+
+    /// Builds a close frame. The reason is truncated so that the payload fits
+    /// in a control frame. See https://www.rfc-editor.org/rfc/rfc6455#section-5.5.1
+    pub fn close(code: u16, reason: &str) -> Self {
+        let mut end = std::cmp::min(reason.len(), 123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason[..end].as_bytes());
+        Self::new(Opcode::Close, payload)
+    }
+
+    /// Parses a frame from the given slice, unmasking its payload if needed.
+    ///
+    /// Frames with a payload larger than [DEFAULT_MAX_PAYLOAD_LEN] are
+    /// rejected, see [Frame::parse_with_max_len].
+    pub fn parse(i: &[u8]) -> IResult<&[u8], Self, ParseError> {
+        Self::parse_with_max_len(i, DEFAULT_MAX_PAYLOAD_LEN)
+    }
+
+    /// Like [Frame::parse], but rejects frames with a payload larger than
+    /// `max_len` with [ParseErrorKind::FrameTooLarge], before buffering it.
+    pub fn parse_with_max_len(i: &[u8], max_len: u32) -> IResult<&[u8], Self, ParseError> {
+        finish(
+            i,
+            Self::raw_parse(i, max_len),
+            ParseErrorKind::InvalidWebSocketFrame,
+        )
+    }
+
+    fn raw_parse(i: &[u8], max_len: u32) -> RawResult<'_, Self> {
+        let start = i;
+        let invalid =
+            || nom::Err::Failure(RawError::new(start, ParseErrorKind::InvalidWebSocketFrame));
+
+        let (i, b0) = be_u8(i)?;
+        let (i, b1) = be_u8(i)?;
+        let fin = b0 & 0x80 != 0;
+        // no extensions are negotiated, so the reserved bits must be unset
+        if b0 & 0x70 != 0 {
+            return Err(invalid());
+        }
+        let opcode = Opcode::from_repr(b0 & 0x0f).ok_or_else(invalid)?;
+        let masked = b1 & 0x80 != 0;
+
+        // lengths must use the shortest encoding possible
+        let (i, len) = match b1 & 0x7f {
+            126 => {
+                let (i, len) = be_u16(i)?;
+                if len < 126 {
+                    return Err(invalid());
+                }
+                (i, len as u64)
+            }
+            127 => {
+                let (i, len) = be_u64(i)?;
+                if len <= u16::MAX as u64 || len >> 63 != 0 {
+                    return Err(invalid());
+                }
+                (i, len)
+            }
+            len => (i, len as u64),
+        };
+        if opcode.is_control() && (!fin || len > 125) {
+            return Err(invalid());
+        }
+        if len > max_len as u64 {
+            return Err(nom::Err::Failure(RawError::new(
+                start,
+                ParseErrorKind::FrameTooLarge {
+                    len: len.try_into().unwrap_or(u32::MAX),
+                    max: max_len,
+                },
+            )));
+        }
+
+        let (i, mask) = if masked {
+            let (i, mask) = take(4_usize)(i)?;
+            (i, Some([mask[0], mask[1], mask[2], mask[3]]))
+        } else {
+            (i, None)
+        };
+        let (i, payload) = take(len)(i)?;
+
+        let mut payload = payload.to_vec();
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+        let frame = Frame {
+            fin,
+            opcode,
+            mask,
+            payload,
+        };
+        Ok((i, frame))
+    }
+
+    /// Serializes the frame, masking the payload if [Frame::mask] is set
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(((self.fin as u8) << 7) | self.opcode.repr());
+
+        let mask_bit = (self.mask.is_some() as u8) << 7;
+        let len = self.payload.len();
+        if len < 126 {
+            out.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        match self.mask {
+            Some(mask) => {
+                out.extend_from_slice(&mask);
+                let payload_start = out.len();
+                out.extend_from_slice(&self.payload);
+                apply_mask(&mut out[payload_start..], mask);
+            }
+            None => out.extend_from_slice(&self.payload),
+        }
+    }
+
+    /// Writes a frame to an [AsyncWrite].
+    pub async fn write(&self, w: &mut (dyn AsyncWrite + Unpin)) -> color_eyre::Result<()> {
+        let mut buf = Vec::with_capacity(14 + self.payload.len());
+        self.encode(&mut buf);
+        w.write_all(&buf).await?;
+        Ok(())
+    }
+}
+
+/// Masking and unmasking are the same operation, see
+/// https://www.rfc-editor.org/rfc/rfc6455#section-5.3
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use httplib::http1::{BodyReader, Framing};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+/// A stream that hands out its data one byte per read, like the slowest
+/// possible network would
+struct Trickle(&'static [u8]);
+
+impl AsyncRead for Trickle {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some((&first, rest)) = self.0.split_first() {
+            out.put_slice(&[first]);
+            self.0 = rest;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+async fn read_body(reader: &mut BodyReader<Trickle>) -> io::Result<String> {
+    let mut out = String::new();
+    reader.read_to_string(&mut out).await?;
+    Ok(out)
+}
+
+#[tokio::test]
+async fn content_length_with_leftover() {
+    // part of the body came with the head
+    let mut reader = BodyReader::new(
+        Trickle(b"lo worldGET /next"),
+        b"hel",
+        Framing::ContentLength(11),
+    );
+    assert_eq!(read_body(&mut reader).await.unwrap(), "hello world");
+    assert!(reader.is_done());
+
+    // what comes next is left alone
+    let (mut stream, leftover) = reader.into_inner();
+    let mut rest = leftover.to_vec();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest, b"GET /next");
+
+    // the whole body may be in the leftover, and then some
+    let mut reader = BodyReader::new(Trickle(b""), b"hiHTTP/1.1", Framing::ContentLength(2));
+    assert_eq!(read_body(&mut reader).await.unwrap(), "hi");
+    assert_eq!(&reader.into_inner().1[..], b"HTTP/1.1");
+}
+
+#[tokio::test]
+async fn chunked_with_extensions_and_trailers() {
+    let mut reader = BodyReader::new(
+        Trickle(
+            b"5;name=value\r\nhello\r\n\
+              6;quoted=\"a;b\"\r\n world\r\n\
+              0\r\nexpires: never\r\nx-checksum: 42\r\n\r\nnext",
+        ),
+        b"",
+        Framing::Chunked,
+    );
+    assert!(reader.trailers().is_none());
+    assert_eq!(read_body(&mut reader).await.unwrap(), "hello world");
+    assert!(reader.is_done());
+
+    let trailers = reader.trailers().unwrap();
+    assert_eq!(trailers.get("expires"), Some("never"));
+    assert_eq!(trailers.get("x-checksum"), Some("42"));
+    drop(trailers);
+
+    let (mut stream, leftover) = reader.into_inner();
+    let mut rest = leftover.to_vec();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest, b"next");
+}
+
+#[tokio::test]
+async fn close_delimited() {
+    let mut reader = BodyReader::new(
+        Trickle(b"until the very end"),
+        b"read ",
+        Framing::CloseDelimited,
+    );
+    assert!(!reader.is_done());
+    assert_eq!(
+        read_body(&mut reader).await.unwrap(),
+        "read until the very end"
+    );
+    assert!(reader.is_done());
+}
+
+#[tokio::test]
+async fn no_body() {
+    let mut reader = BodyReader::new(Trickle(b"HTTP/1.1"), b"", Framing::None);
+    assert!(reader.is_done());
+    assert_eq!(read_body(&mut reader).await.unwrap(), "");
+    let (_, leftover) = reader.into_inner();
+    assert!(leftover.is_empty());
+}
+
+#[tokio::test]
+async fn truncated_bodies_are_errors() {
+    // the connection closes before the body is over: that's not a short body
+    let mut reader = BodyReader::new(Trickle(b"lo"), b"hel", Framing::ContentLength(11));
+    let err = read_body(&mut reader).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(!reader.is_done());
+
+    for body in [
+        &b"5\r\nhello\r\n"[..],
+        b"5\r\nhel",
+        b"5\r\nhello\r\n0\r\n",
+        b"5\r\nhello\r\n0\r\nexpires: never\r\n",
+    ] {
+        let mut reader = BodyReader::new(Trickle(body), b"", Framing::Chunked);
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).await.unwrap_err();
+        assert_eq!(
+            err.kind(),
+            io::ErrorKind::UnexpectedEof,
+            "{:?}",
+            String::from_utf8_lossy(body)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use httplib::{
+    http1::{chunk_size, Chunk, ChunkedDecoder, ParserLimits},
+    ParseErrorKind,
+};
+
+#[test]
+fn chunk_size_lines() {
+    assert_eq!(chunk_size(b"1f4\r\nrest").unwrap(), (&b"rest"[..], 0x1f4));
+    assert_eq!(
+        chunk_size(b"0; name=\"quoted value\"; other\r\n").unwrap(),
+        (&b""[..], 0)
+    );
+    assert!(matches!(chunk_size(b"1f4"), Err(nom::Err::Incomplete(_))));
+}
+
+#[test]
+fn decodes_chunks_and_trailers() {
+    let mut decoder = ChunkedDecoder::new();
+    let input = b"5\r\nhello\r\n0\r\nexpires: never\r\n\r\n";
+
+    let (rest, chunk) = decoder.decode(input).unwrap();
+    assert_eq!(chunk, Chunk::Data(b"hello"));
+    let (rest, chunk) = decoder.decode(rest).unwrap();
+    let Chunk::End(trailers) = chunk else {
+        panic!("expected the end, got {chunk:?}");
+    };
+    assert_eq!(trailers.get("expires"), Some("never"));
+    assert!(rest.is_empty());
+    assert!(decoder.is_done());
+}
+
+#[test]
+fn chunk_size_line_is_capped() {
+    let mut line = b"1".to_vec();
+    while line.len() < 200_000 {
+        line.extend_from_slice(b";a");
+    }
+
+    // no CRLF in sight: this must not wait for more input forever
+    let err = chunk_size(&line).unwrap_err();
+    let nom::Err::Failure(e) = err else {
+        panic!("expected a failure, got {err:?}");
+    };
+    assert_eq!(e.kind, ParseErrorKind::InvalidChunk);
+
+    let limits = ParserLimits {
+        max_header_line_len: 16,
+        ..Default::default()
+    };
+    let mut decoder = ChunkedDecoder::with_limits(limits);
+    assert!(matches!(
+        decoder.decode(b"1;a;a;a;a;a;a;a;a;a"),
+        Err(nom::Err::Failure(e)) if e.kind == ParseErrorKind::InvalidChunk
+    ));
+    let mut decoder = ChunkedDecoder::with_limits(limits);
+    assert_eq!(decoder.decode(b"1;a;a\r\nx").unwrap().1, Chunk::Data(b"x"));
+}
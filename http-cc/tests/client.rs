@@ -0,0 +1,208 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{ok, scripted, serve, Step};
+use httplib::http1::{ClientConnection, Framing, RequestHead, ServerResponse};
+use tokio::{io::AsyncReadExt, net::TcpStream, sync::mpsc};
+
+/// Answers every request with its target
+async fn echo_target() -> std::net::SocketAddr {
+    serve(|req: RequestHead, _| async move { ok(req.target()) }).await
+}
+
+fn get(target: &str) -> RequestHead {
+    RequestHead::builder("GET", target)
+        .header("host", "localhost")
+        .build()
+        .unwrap()
+}
+
+fn put_expecting_continue(body: &[u8]) -> RequestHead {
+    RequestHead::builder("PUT", "/")
+        .header("host", "localhost")
+        .header("expect", "100-continue")
+        .framing(Framing::ContentLength(body.len() as u64))
+        .build()
+        .unwrap()
+}
+
+async fn body_of(conn: &mut ClientConnection<TcpStream>, target: &str) -> String {
+    let (res, mut body) = conn.send(&get(target), &[]).await.unwrap();
+    assert_eq!(res.status, 200);
+    let mut out = String::new();
+    body.read_to_string(&mut out).await.unwrap();
+    out
+}
+
+#[tokio::test]
+async fn keep_alive_reuses_the_connection() {
+    let addr = echo_target().await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+
+    for target in ["/a", "/b", "/c"] {
+        assert_eq!(body_of(&mut conn, target).await, target);
+        assert!(conn.is_open());
+        assert!(conn.is_reusable());
+    }
+}
+
+#[tokio::test]
+async fn unread_bodies_are_drained() {
+    let addr = serve(|req: RequestHead, _| async move {
+        match req.target() {
+            "/big" => ok("x".repeat(100_000)),
+            target => ok(target),
+        }
+    })
+    .await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+
+    {
+        // the body is left unread
+        let (res, _body) = conn.send(&get("/big"), &[]).await.unwrap();
+        assert_eq!(res.status, 200);
+    }
+    // so the next request has to skip past it to find its response
+    assert_eq!(body_of(&mut conn, "/next").await, "/next");
+}
+
+#[tokio::test]
+async fn unread_chunked_bodies_are_drained() {
+    // `Server` always sends content-length, so this one has to be scripted
+    let addr = scripted(|req| match req.target() {
+        "/chunked" => vec![Step::Write(
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+        )],
+        _ => vec![Step::Write(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n/next",
+        )],
+    })
+    .await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+
+    {
+        let (res, _body) = conn.send(&get("/chunked"), &[]).await.unwrap();
+        assert_eq!(res.status, 200);
+    }
+    assert_eq!(body_of(&mut conn, "/next").await, "/next");
+}
+
+#[tokio::test]
+async fn connection_close_is_honored() {
+    let addr = serve(|_, _| async {
+        ServerResponse::builder(200)
+            .header("connection", "close")
+            .body("hi")
+            .build()
+            .unwrap()
+    })
+    .await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+
+    assert_eq!(body_of(&mut conn, "/").await, "hi");
+    assert!(!conn.is_open());
+    let err = conn.send(&get("/"), &[]).await.err().unwrap();
+    assert_eq!(err.to_string(), "connection is closed");
+}
+
+#[tokio::test]
+async fn server_closing_between_requests_is_noticed() {
+    // the server says nothing about closing, but does it after one request,
+    // like it would after its keep-alive timeout
+    let addr = scripted(|req| {
+        let res: &[u8] = match req.target() {
+            "/a" => b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n/a",
+            _ => b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n/b",
+        };
+        vec![Step::Write(res), Step::Close]
+    })
+    .await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+
+    assert_eq!(body_of(&mut conn, "/a").await, "/a");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(conn.is_open(), "nothing happened during the last request");
+    assert!(!conn.is_reusable());
+    assert!(!conn.is_open());
+
+    // a caller that doesn't check gets an error before anything is sent, and
+    // a new connection works
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+    assert_eq!(body_of(&mut conn, "/b").await, "/b");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let err = conn.send(&get("/c"), &[]).await.err().unwrap();
+    assert_eq!(err.to_string(), "server closed the connection");
+    assert!(!conn.is_open());
+}
+
+#[tokio::test]
+async fn continue_then_final_response() {
+    // `Server` sends the `100 Continue` itself, before reading the body
+    let addr = serve(|_, body| async move { ok(body) }).await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+    conn.set_continue_timeout(Duration::from_secs(5));
+
+    let started = Instant::now();
+    let req = put_expecting_continue(b"hello");
+    let (res, mut body) = conn.send(&req, b"hello").await.unwrap();
+    assert_eq!(res.status, 200);
+    let mut out = String::new();
+    body.read_to_string(&mut out).await.unwrap();
+    assert_eq!(out, "hello");
+    // it didn't wait for the timeout
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(conn.is_reusable());
+}
+
+#[tokio::test]
+async fn early_final_response_skips_the_body() {
+    // the server refuses the body right away, and tells us what it got after
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let addr = scripted(move |_| {
+        vec![
+            Step::Write(b"HTTP/1.1 413 Payload Too Large\r\ncontent-length: 0\r\n\r\n"),
+            Step::Drain(tx.clone()),
+        ]
+    })
+    .await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+    conn.set_continue_timeout(Duration::from_secs(5));
+
+    let req = put_expecting_continue(b"hello");
+    let (res, _) = conn.send(&req, b"hello").await.unwrap();
+    assert_eq!(res.status, 413);
+    // the server is still expecting a body it doesn't want, so the
+    // connection can't be used for anything else
+    assert!(!conn.is_open());
+    assert!(!conn.is_reusable());
+    drop(conn);
+
+    assert_eq!(rx.recv().await.unwrap(), b"", "the body was sent anyway");
+}
+
+#[tokio::test]
+async fn body_is_sent_after_the_continue_timeout() {
+    // an old server that doesn't know about `expect`, and waits for the body
+    let addr = scripted(|_| {
+        vec![
+            Step::ReadBody(5),
+            Step::Write(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"),
+        ]
+    })
+    .await;
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+    let timeout = Duration::from_millis(100);
+    conn.set_continue_timeout(timeout);
+
+    let started = Instant::now();
+    let req = put_expecting_continue(b"hello");
+    let (res, mut body) = conn.send(&req, b"hello").await.unwrap();
+    assert_eq!(res.status, 200);
+    assert!(started.elapsed() >= timeout);
+    let mut out = String::new();
+    body.read_to_string(&mut out).await.unwrap();
+    assert_eq!(out, "ok");
+    assert!(conn.is_reusable());
+}
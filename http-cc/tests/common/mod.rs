@@ -0,0 +1,106 @@
+//! Test servers shared by the integration tests. Not every test uses all of
+//! them.
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use bytes::{Buf, BytesMut};
+use httplib::http1::{Handler, RequestHead, Server, ServerResponse};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+/// Runs `server` in the background, and returns its address
+pub fn spawn<H: Handler>(server: Server<H>) -> SocketAddr {
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+    addr
+}
+
+/// Starts a [Server] on a random port, with default settings
+pub async fn serve<H: Handler>(handler: H) -> SocketAddr {
+    spawn(Server::bind("127.0.0.1:0", handler).await.unwrap())
+}
+
+/// A `200 OK` with `body`
+pub fn ok(body: impl Into<Vec<u8>>) -> ServerResponse {
+    ServerResponse::builder(200).body(body).build().unwrap()
+}
+
+/// Reads a request head off a raw stream. What comes after it stays in
+/// `buf`. Returns `None` if the client hung up before sending a request.
+pub async fn read_head(stream: &mut TcpStream, buf: &mut BytesMut) -> Option<RequestHead> {
+    loop {
+        match RequestHead::parse(buf) {
+            Ok((rest, head)) => {
+                let consumed = buf.len() - rest.len();
+                buf.advance(consumed);
+                return Some(head);
+            }
+            Err(nom::Err::Incomplete(_)) => {}
+            Err(e) => panic!("client sent an invalid request head: {e:?}"),
+        }
+        if stream.read_buf(buf).await.unwrap() == 0 {
+            assert!(buf.is_empty(), "client hung up in the middle of a request");
+            return None;
+        }
+    }
+}
+
+/// What a [scripted] server does once it has read a request head, in order
+pub enum Step {
+    Write(&'static [u8]),
+    Sleep(Duration),
+    /// Reads that many bytes of request body
+    ReadBody(usize),
+    /// Reads whatever else the client sends until it hangs up, and sends it
+    /// over the channel
+    Drain(mpsc::UnboundedSender<Vec<u8>>),
+    /// Hangs up, whether or not there are more requests
+    Close,
+}
+
+/// A server for the byte-level misbehaviour [Server] won't do, like splitting
+/// a response across writes, or hanging up without a word. For every request
+/// head, on any connection, it runs the steps `script` returns.
+pub async fn scripted<F>(script: F) -> SocketAddr
+where
+    F: Fn(&RequestHead) -> Vec<Step> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let script = Arc::new(script);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.set_nodelay(true).unwrap();
+            let script = script.clone();
+            tokio::spawn(async move {
+                let mut buf = BytesMut::new();
+                while let Some(head) = read_head(&mut stream, &mut buf).await {
+                    for step in script(&head) {
+                        match step {
+                            Step::Write(data) => stream.write_all(data).await.unwrap(),
+                            Step::Sleep(delay) => tokio::time::sleep(delay).await,
+                            Step::ReadBody(len) => {
+                                while buf.len() < len {
+                                    assert_ne!(stream.read_buf(&mut buf).await.unwrap(), 0);
+                                }
+                                buf.advance(len);
+                            }
+                            Step::Drain(tx) => {
+                                while matches!(stream.read_buf(&mut buf).await, Ok(n) if n > 0) {}
+                                tx.send(buf.to_vec()).unwrap();
+                                return;
+                            }
+                            Step::Close => return,
+                        }
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
@@ -0,0 +1,159 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use httplib::connect::{happy_eyeballs, interleave, Outcome};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+const V6: IpAddr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+const V4: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// A listener that never accepts, and whose backlog is full: the kernel drops
+/// the SYNs of new connections, so they hang like they would on a network
+/// that silently drops packets. It works as long as the returned value lives.
+async fn blackhole(ip: IpAddr) -> (SocketAddr, (TcpListener, Vec<TcpStream>)) {
+    let socket = match ip {
+        IpAddr::V4(_) => TcpSocket::new_v4(),
+        IpAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .unwrap();
+    socket.bind(SocketAddr::new(ip, 0)).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut queued = Vec::new();
+    for _ in 0..64 {
+        let connect = TcpStream::connect(addr);
+        match tokio::time::timeout(Duration::from_millis(100), connect).await {
+            Ok(stream) => queued.push(stream.unwrap()),
+            Err(_) => return (addr, (listener, queued)),
+        }
+    }
+    panic!("couldn't fill the backlog of {addr}");
+}
+
+/// A listener that accepts (in the kernel) every connection
+async fn listening(ip: IpAddr) -> (SocketAddr, TcpListener) {
+    let listener = TcpListener::bind(SocketAddr::new(ip, 0)).await.unwrap();
+    (listener.local_addr().unwrap(), listener)
+}
+
+/// An address where nothing listens, so connecting is refused right away
+async fn refusing(ip: IpAddr) -> SocketAddr {
+    listening(ip).await.0
+}
+
+fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn interleave_order() {
+    let addrs = [
+        addr("[2001:db8::1]:443"),
+        addr("[2001:db8::2]:443"),
+        addr("[2001:db8::3]:443"),
+        addr("192.0.2.1:443"),
+        addr("192.0.2.2:443"),
+    ];
+    assert_eq!(
+        interleave(&addrs),
+        [addrs[0], addrs[3], addrs[1], addrs[4], addrs[2]]
+    );
+
+    // the first address decides which family goes first
+    let addrs = [
+        addr("192.0.2.1:443"),
+        addr("192.0.2.2:443"),
+        addr("192.0.2.3:443"),
+        addr("[2001:db8::1]:443"),
+    ];
+    assert_eq!(interleave(&addrs), [addrs[0], addrs[3], addrs[1], addrs[2]]);
+    assert!(interleave(&[]).is_empty());
+}
+
+#[tokio::test]
+async fn blackholed_ipv6_only_costs_the_delay() {
+    let delay = Duration::from_millis(100);
+    let (v6, _blackhole) = blackhole(V6).await;
+    let (v4, _listener) = listening(V4).await;
+
+    let connected = happy_eyeballs(&[v6, v4], delay).await.unwrap();
+    assert_eq!(connected.addr, v4);
+
+    let [first, second] = &connected.attempts[..] else {
+        panic!("expected 2 attempts: {:?}", connected.attempts);
+    };
+    assert_eq!(first.addr, v6);
+    assert!(first.started < Duration::from_millis(20), "{first:?}");
+    assert!(matches!(first.outcome, Outcome::Canceled), "{first:?}");
+    assert_eq!(first.finished, None);
+
+    assert_eq!(second.addr, v4);
+    assert!(matches!(second.outcome, Outcome::Connected), "{second:?}");
+    // the second attempt waited for the delay, not much longer
+    assert!(second.started >= delay, "{second:?}");
+    assert!(second.started < delay * 2, "{second:?}");
+}
+
+#[tokio::test]
+async fn attempts_are_staggered() {
+    let delay = Duration::from_millis(50);
+    let (v6_a, _blackhole_v6_a) = blackhole(V6).await;
+    let (v6_b, _blackhole_v6_b) = blackhole(V6).await;
+    let (v4_a, _blackhole_v4_a) = blackhole(V4).await;
+    let (v4_b, _listener) = listening(V4).await;
+
+    let addrs = [v6_a, v6_b, v4_a, v4_b];
+    let connected = happy_eyeballs(&addrs, delay).await.unwrap();
+    assert_eq!(connected.addr, v4_b);
+
+    // attempts are made in interleaved order, one per delay
+    let order: Vec<_> = connected.attempts.iter().map(|a| a.addr).collect();
+    assert_eq!(order, interleave(&addrs));
+    assert_eq!(order, [v6_a, v4_a, v6_b, v4_b]);
+    for (i, attempt) in connected.attempts.iter().enumerate() {
+        let expected = delay * i as u32;
+        assert!(attempt.started >= expected, "{attempt:?}");
+        assert!(attempt.started < expected + delay / 2 * 3, "{attempt:?}");
+    }
+}
+
+#[tokio::test]
+async fn failures_start_the_next_attempt_early() {
+    // refused right away, so there's no reason to wait for the delay
+    let delay = Duration::from_secs(5);
+    let v6 = refusing(V6).await;
+    let (v4, _listener) = listening(V4).await;
+
+    let connected = happy_eyeballs(&[v6, v4], delay).await.unwrap();
+    assert_eq!(connected.addr, v4);
+    let [first, second] = &connected.attempts[..] else {
+        panic!("expected 2 attempts: {:?}", connected.attempts);
+    };
+    assert!(matches!(first.outcome, Outcome::Failed(_)), "{first:?}");
+    assert!(second.started < Duration::from_secs(1), "{second:?}");
+}
+
+#[tokio::test]
+async fn every_attempt_fails() {
+    let addrs = [refusing(V6).await, refusing(V4).await];
+    let err = happy_eyeballs(&addrs, Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert_eq!(err.attempts.len(), 2);
+    assert!(err
+        .attempts
+        .iter()
+        .all(|a| matches!(a.outcome, Outcome::Failed(_)) && a.finished.is_some()));
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::ConnectionRefused
+    );
+
+    let err = happy_eyeballs(&[], Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "no addresses to connect to");
+}
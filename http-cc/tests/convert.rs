@@ -0,0 +1,238 @@
+use httplib::{
+    http1::{self, Version},
+    http2::HeaderBlock,
+    ConvertError,
+};
+
+fn block(headers: &[(&str, &[u8])]) -> HeaderBlock {
+    HeaderBlock(
+        headers
+            .iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.to_vec()))
+            .collect(),
+    )
+}
+
+#[test]
+fn http1_request_round_trip() {
+    let (_, req) = http1::request(
+        b"POST /upload?x=1 HTTP/1.1\r\nhost: example.org\r\n\
+          accept: a\r\naccept: b\r\nx-name: Andr\xe9\r\n\r\n",
+    )
+    .unwrap();
+    let converted = http::Request::try_from(&req).unwrap();
+    assert_eq!(converted.method(), http::Method::POST);
+    assert_eq!(converted.uri(), "/upload?x=1");
+    assert_eq!(converted.version(), http::Version::HTTP_11);
+    let accept: Vec<_> = converted.headers().get_all("accept").iter().collect();
+    assert_eq!(accept, ["a", "b"]);
+    // latin-1 makes it through untouched, even if it isn't a valid str
+    assert_eq!(converted.headers()["x-name"].as_bytes(), b"Andr\xe9");
+
+    let back = http1::Request::try_from(&converted).unwrap();
+    assert_eq!((back.method, back.target), ("POST", "/upload?x=1"));
+    assert_eq!(back.version, Version::Http11);
+    assert_eq!(back.headers.get("host"), Some("example.org"));
+    assert_eq!(back.headers.get_bytes("x-name"), Some(&b"Andr\xe9"[..]));
+}
+
+#[test]
+fn http1_request_from_absolute_uri() {
+    // the authority becomes the host header, unless there's one already
+    let req = http::Request::get("http://example.org:8080/a?b")
+        .body(())
+        .unwrap();
+    let converted = http1::Request::try_from(&req).unwrap();
+    assert_eq!(converted.target, "/a?b");
+    assert_eq!(converted.headers.get("host"), Some("example.org:8080"));
+
+    let req = http::Request::get("http://example.org/")
+        .header("host", "elsewhere.org")
+        .body(())
+        .unwrap();
+    let converted = http1::Request::try_from(&req).unwrap();
+    assert_eq!(converted.headers.get_all("host").count(), 1);
+    assert_eq!(converted.headers.get("host"), Some("elsewhere.org"));
+
+    let req = http::Request::connect("example.org:443").body(()).unwrap();
+    assert_eq!(
+        http1::Request::try_from(&req).unwrap().target,
+        "example.org:443"
+    );
+
+    let req = http::Request::get("/")
+        .version(http::Version::HTTP_2)
+        .body(())
+        .unwrap();
+    assert!(matches!(
+        http1::Request::try_from(&req),
+        Err(ConvertError::UnsupportedVersion(http::Version::HTTP_2))
+    ));
+}
+
+#[test]
+fn http1_response_round_trip() {
+    let (_, res) = http1::response(
+        b"HTTP/1.0 404 Introuvable\r\ncontent-length: 0\r\nx-name: Andr\xe9\r\n\r\n",
+    )
+    .unwrap();
+    let converted = http::Response::try_from(&res).unwrap();
+    assert_eq!(converted.status(), 404);
+    assert_eq!(converted.version(), http::Version::HTTP_10);
+    assert_eq!(converted.headers()["x-name"].as_bytes(), b"Andr\xe9");
+
+    // the reason phrase is lost on the way, and the canonical one is used
+    let back = http1::Response::try_from(&converted).unwrap();
+    assert_eq!(back.status, 404);
+    assert_eq!(back.status_text, b"Not Found");
+    assert_eq!(back.version, Version::Http10);
+    assert_eq!(back.headers.get("content-length"), Some("0"));
+}
+
+#[test]
+fn http2_request_round_trip() {
+    let req = http::Request::post("https://example.org/upload?x=1")
+        .header("accept", "a")
+        .header("accept", "b")
+        .header("x-name", &b"Andr\xe9"[..])
+        .body(())
+        .unwrap();
+    let converted = HeaderBlock::try_from(&req).unwrap();
+    assert_eq!(
+        converted.0[..4],
+        block(&[
+            (":method", b"POST"),
+            (":scheme", b"https"),
+            (":authority", b"example.org"),
+            (":path", b"/upload?x=1"),
+        ])
+        .0
+    );
+    assert_eq!(
+        converted.get_all("accept").collect::<Vec<_>>(),
+        [b"a", b"b"]
+    );
+    assert_eq!(converted.get("x-name"), Some(&b"Andr\xe9"[..]));
+
+    let back = http::Request::try_from(&converted).unwrap();
+    assert_eq!(back.method(), http::Method::POST);
+    assert_eq!(back.uri(), "https://example.org/upload?x=1");
+    assert_eq!(back.version(), http::Version::HTTP_2);
+    assert_eq!(back.headers(), req.headers());
+}
+
+#[test]
+fn http2_authority_from_host() {
+    // a relative URI, like one converted from HTTP/1.1, gets its authority
+    // from the host header, which isn't sent on its own
+    let req = http::Request::get("/a")
+        .header("host", "example.org")
+        .body(())
+        .unwrap();
+    let converted = HeaderBlock::try_from(&req).unwrap();
+    assert_eq!(converted.get(":authority"), Some(&b"example.org"[..]));
+    assert_eq!(converted.get(":scheme"), Some(&b"https"[..]));
+    assert_eq!(converted.get("host"), None);
+
+    let req = http::Request::get("/a").body(()).unwrap();
+    assert!(matches!(
+        HeaderBlock::try_from(&req),
+        Err(ConvertError::MissingAuthority)
+    ));
+}
+
+#[test]
+fn http2_connection_specific_headers_are_stripped() {
+    let req = http::Request::get("https://example.org/")
+        .header("connection", "keep-alive, x-hop")
+        .header("keep-alive", "timeout=5")
+        .header("proxy-connection", "keep-alive")
+        .header("transfer-encoding", "chunked")
+        .header("upgrade", "websocket")
+        .header("accept", "*/*")
+        .body(())
+        .unwrap();
+    let converted = HeaderBlock::try_from(&req).unwrap();
+    let regular: Vec<_> = converted
+        .0
+        .iter()
+        .filter(|(k, _)| !k.starts_with(b":"))
+        .collect();
+    assert_eq!(regular, [&(b"accept".to_vec(), b"*/*".to_vec())]);
+
+    let res = http::Response::builder()
+        .status(200)
+        .header("connection", "close")
+        .header("content-type", "text/plain")
+        .body(())
+        .unwrap();
+    assert_eq!(
+        HeaderBlock::try_from(&res).unwrap(),
+        block(&[(":status", b"200"), ("content-type", b"text/plain")])
+    );
+}
+
+#[test]
+fn http2_response_round_trip() {
+    let res = http::Response::builder()
+        .status(404)
+        .header("x-name", &b"Andr\xe9"[..])
+        .body(())
+        .unwrap();
+    let converted = HeaderBlock::try_from(&res).unwrap();
+    let back = http::Response::try_from(&converted).unwrap();
+    assert_eq!(back.status(), 404);
+    assert_eq!(back.version(), http::Version::HTTP_2);
+    assert_eq!(back.headers(), res.headers());
+
+    // there's no upgrading an HTTP/2 connection
+    let res = http::Response::builder().status(101).body(()).unwrap();
+    assert!(matches!(
+        HeaderBlock::try_from(&res),
+        Err(ConvertError::InvalidPseudoHeader(":status"))
+    ));
+}
+
+#[test]
+fn http2_invalid_pseudo_headers() {
+    let missing_path = block(&[
+        (":method", b"GET"),
+        (":scheme", b"https"),
+        (":authority", b"example.org"),
+    ]);
+    assert!(matches!(
+        http::Request::try_from(&missing_path),
+        Err(ConvertError::InvalidPseudoHeader(":path"))
+    ));
+
+    let not_utf8 = block(&[
+        (":method", b"GET"),
+        (":scheme", b"https"),
+        (":authority", b"example.org"),
+        (":path", b"/caf\xe9"),
+    ]);
+    assert!(matches!(
+        http::Request::try_from(&not_utf8),
+        Err(ConvertError::InvalidPseudoHeader(":path"))
+    ));
+
+    let bad_method = block(&[
+        (":method", b"G T"),
+        (":scheme", b"https"),
+        (":authority", b"example.org"),
+        (":path", b"/"),
+    ]);
+    assert!(matches!(
+        http::Request::try_from(&bad_method),
+        Err(ConvertError::Http(_))
+    ));
+
+    assert!(matches!(
+        http::Response::try_from(&block(&[("content-type", b"text/plain")])),
+        Err(ConvertError::InvalidPseudoHeader(":status"))
+    ));
+    assert!(matches!(
+        http::Response::try_from(&block(&[(":status", b"2000")])),
+        Err(ConvertError::Http(_))
+    ));
+}
@@ -0,0 +1,126 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use httplib::dns::{CachingResolver, ResolveFuture, Resolver, StaticResolver};
+
+/// Resolves everything to 192.0.2.1 (except `fail.test`), and counts how
+/// many times it was asked
+#[derive(Default)]
+struct Counting(AtomicUsize);
+
+impl Resolver for Counting {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async move {
+            if host == "fail.test" {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "nope"));
+            }
+            Ok(vec![SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                port,
+            )])
+        })
+    }
+}
+
+async fn resolve(resolver: &dyn Resolver, host: &str) -> Vec<IpAddr> {
+    let addrs = resolver.resolve(host, 443).await.unwrap();
+    assert!(addrs.iter().all(|a| a.port() == 443));
+    addrs.iter().map(|a| a.ip()).collect()
+}
+
+#[tokio::test]
+async fn caching() {
+    let inner = Arc::new(Counting::default());
+    let ttl = Duration::from_millis(100);
+    let resolver = CachingResolver::new(inner.clone(), ttl);
+    let count = || inner.0.load(Ordering::SeqCst);
+
+    resolve(&resolver, "example.org").await;
+    assert_eq!(count(), 1);
+    // within the TTL, whatever the case
+    resolve(&resolver, "EXAMPLE.org").await;
+    assert_eq!(count(), 1);
+    // another port is another entry
+    resolver.resolve("example.org", 80).await.unwrap();
+    assert_eq!(count(), 2);
+
+    tokio::time::sleep(ttl + Duration::from_millis(20)).await;
+    resolve(&resolver, "example.org").await;
+    assert_eq!(count(), 3);
+    resolve(&resolver, "example.org").await;
+    assert_eq!(count(), 3);
+
+    resolver.clear();
+    resolve(&resolver, "example.org").await;
+    assert_eq!(count(), 4);
+
+    // failures are asked again every time
+    for expected in [5, 6] {
+        assert!(resolver.resolve("fail.test", 443).await.is_err());
+        assert_eq!(count(), expected);
+    }
+}
+
+#[tokio::test]
+async fn hosts_file() {
+    let resolver = StaticResolver::from_hosts(
+        "# comment\n\
+         127.0.0.1\tlocalhost  Local.Test # trailing comment\n\
+         ::1 localhost ip6-localhost\n\
+         \n\
+         not-an-ip ignored.test\n\
+         192.0.2.1 # no names\n\
+         2001:db8::1 v6.test\n",
+    );
+
+    let v4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    // in the order they appear, across lines
+    assert_eq!(resolve(&resolver, "localhost").await, [v4, v6]);
+    assert_eq!(resolve(&resolver, "local.test").await, [v4]);
+    assert_eq!(resolve(&resolver, "ip6-localhost").await, [v6]);
+    assert_eq!(
+        resolve(&resolver, "v6.test").await,
+        ["2001:db8::1".parse::<IpAddr>().unwrap()]
+    );
+
+    for host in ["ignored.test", "comment", "no"] {
+        let err = resolver.resolve(host, 443).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound, "{host}");
+    }
+}
+
+#[tokio::test]
+async fn static_lookups() {
+    let resolver = StaticResolver::new()
+        .insert("Example.ORG", IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)))
+        .insert("example.org", IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)));
+    // case-insensitive both ways
+    for host in ["example.org", "EXAMPLE.ORG", "eXaMpLe.OrG"] {
+        assert_eq!(resolve(&resolver, host).await.len(), 2, "{host}");
+    }
+    // IP literals don't need an entry
+    assert_eq!(
+        resolve(&resolver, "::1").await,
+        [IpAddr::V6(Ipv6Addr::LOCALHOST)]
+    );
+    assert!(resolver.resolve("example.net", 443).await.is_err());
+
+    let counting = Arc::new(Counting::default());
+    let resolver = resolver.fallback(counting.clone());
+    assert_eq!(resolve(&resolver, "example.org").await.len(), 2);
+    assert_eq!(counting.0.load(Ordering::SeqCst), 0);
+    assert_eq!(
+        resolve(&resolver, "example.net").await,
+        [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+    assert_eq!(counting.0.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,83 @@
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder};
+use httplib::encoding::{content_codings, decode, ContentCoding, UnsupportedCoding};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const TEXT: &[u8] = b"The quick brown fox jumps over the lazy dog, again and again and again";
+
+async fn read_all(mut r: impl AsyncRead + Unpin) -> Vec<u8> {
+    let mut out = Vec::new();
+    r.read_to_end(&mut out).await.unwrap();
+    out
+}
+
+/// Applies `coding` to `data`, like a server would
+async fn encode(data: &[u8], coding: ContentCoding) -> Vec<u8> {
+    match coding {
+        ContentCoding::Gzip => read_all(GzipEncoder::new(data)).await,
+        ContentCoding::Deflate => read_all(ZlibEncoder::new(data)).await,
+        ContentCoding::Brotli => read_all(BrotliEncoder::new(data)).await,
+        ContentCoding::Identity => data.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn round_trips() {
+    for coding in [
+        ContentCoding::Gzip,
+        ContentCoding::Deflate,
+        ContentCoding::Brotli,
+        ContentCoding::Identity,
+    ] {
+        let encoded = encode(TEXT, coding).await;
+        if coding != ContentCoding::Identity {
+            assert_ne!(encoded, TEXT, "{coding:?}");
+        }
+        assert_eq!(
+            read_all(decode(&encoded[..], &[coding])).await,
+            TEXT,
+            "{coding:?}"
+        );
+    }
+    assert_eq!(read_all(decode(TEXT, &[])).await, TEXT);
+}
+
+#[tokio::test]
+async fn stacked_codings_are_undone_in_reverse() {
+    // `content-encoding: gzip, br` means gzip was applied first, then br
+    let codings = content_codings([&b"gzip, br"[..]]).unwrap();
+    assert_eq!(codings, [ContentCoding::Gzip, ContentCoding::Brotli]);
+
+    let gzipped = encode(TEXT, ContentCoding::Gzip).await;
+    let encoded = encode(&gzipped, ContentCoding::Brotli).await;
+    assert_eq!(read_all(decode(&encoded[..], &codings)).await, TEXT);
+
+    // undoing them in the wrong order fails
+    let mut out = Vec::new();
+    let res = decode(&encoded[..], &[ContentCoding::Brotli, ContentCoding::Gzip])
+        .read_to_end(&mut out)
+        .await;
+    assert!(res.is_err());
+}
+
+#[test]
+fn parsing_codings() {
+    // spread over several lines, with odd casing and spacing
+    assert_eq!(
+        content_codings([&b"X-GZIP ,, identity"[..], b"\tdeflate"]).unwrap(),
+        [
+            ContentCoding::Gzip,
+            ContentCoding::Identity,
+            ContentCoding::Deflate
+        ]
+    );
+    assert_eq!(content_codings([]).unwrap(), []);
+    assert_eq!(content_codings([&b""[..]]).unwrap(), []);
+
+    let err = content_codings([&b"gzip, zstd"[..]]).unwrap_err();
+    assert_eq!(err, UnsupportedCoding("zstd".into()));
+    assert_eq!(err.to_string(), "unsupported content-encoding \"zstd\"");
+    assert_eq!(
+        content_codings([&b"compress"[..]]),
+        Err(UnsupportedCoding("compress".into()))
+    );
+}
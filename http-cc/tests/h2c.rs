@@ -0,0 +1,323 @@
+mod common;
+
+use bytes::{Buf, BytesMut};
+use httplib::http2::{
+    ClientConnection, ContinuationFlags, DataFlags, Frame, FrameType, HeaderBlock, HeadersFlags,
+    Setting, SettingsFlags, PREFACE,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// A tiny h2c server: it answers every request with `hello from <path>`, and
+/// sends frames of unknown types along the way, which clients must ignore.
+struct Server {
+    stream: TcpStream,
+    buf: BytesMut,
+    encoder: hpack::Encoder<'static>,
+    decoder: hpack::Decoder<'static>,
+    /// The SETTINGS_HEADER_TABLE_SIZE to advertise, if not the default
+    header_table_size: Option<u32>,
+}
+
+impl Server {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buf: BytesMut::new(),
+            encoder: hpack::Encoder::new(),
+            decoder: hpack::Decoder::new(),
+            header_table_size: None,
+        }
+    }
+
+    async fn read_frame(&mut self) -> Option<Frame> {
+        loop {
+            match Frame::parse(&self.buf) {
+                Ok((rest, frame)) => {
+                    let consumed = self.buf.len() - rest.len();
+                    self.buf.advance(consumed);
+                    return Some(frame);
+                }
+                Err(nom::Err::Incomplete(_)) => {}
+                Err(e) => panic!("client sent an invalid frame: {e:?}"),
+            }
+            if self.stream.read_buf(&mut self.buf).await.unwrap() == 0 {
+                return None;
+            }
+        }
+    }
+
+    async fn respond(&mut self, stream_id: u32, path: &str) {
+        let mut out = Vec::new();
+
+        // an ALTSVC frame, and a reserved (GREASE) type on the request's
+        // stream, see https://www.rfc-editor.org/rfc/rfc8701
+        let mut altsvc = Frame::new(FrameType::Unknown(0xa), 0);
+        altsvc.payload.extend_from_slice(b"\x00\x00h3=\":443\"");
+        let mut grease = Frame::new(FrameType::Unknown(0xfa), stream_id);
+        grease.payload.extend_from_slice(b"ignore me");
+
+        let mut headers = Frame::new(
+            FrameType::Headers(HeadersFlags::EndHeaders.into()),
+            stream_id,
+        );
+        let mut block = HeaderBlock::default();
+        block.push(":status", "200");
+        block.push("content-type", "text/plain");
+        headers.payload.0 = block.encode(&mut self.encoder);
+
+        let mut data = Frame::new(FrameType::Data(DataFlags::EndStream.into()), stream_id);
+        data.payload
+            .extend_from_slice(format!("hello from {path}").as_bytes());
+
+        for frame in [altsvc, grease, headers, data] {
+            frame.write(&mut out).await.unwrap();
+        }
+        self.stream.write_all(&out).await.unwrap();
+    }
+
+    /// Reads an HTTP/1.1 request asking to switch to h2c, and agrees.
+    /// Returns the path that was requested.
+    async fn accept_upgrade(&mut self) -> String {
+        let head = common::read_head(&mut self.stream, &mut self.buf)
+            .await
+            .unwrap();
+        assert_eq!(head.headers().get("upgrade"), Some("h2c"));
+        assert!(head.headers().contains("http2-settings"));
+
+        self.stream
+            .write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\n\
+                  connection: upgrade\r\n\
+                  upgrade: h2c\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        head.target().to_owned()
+    }
+
+    /// Reads the client's preface, and sends our settings
+    async fn start(&mut self) {
+        while self.buf.len() < PREFACE.len() {
+            assert_ne!(self.stream.read_buf(&mut self.buf).await.unwrap(), 0);
+        }
+        assert_eq!(&self.buf[..PREFACE.len()], PREFACE);
+        self.buf.advance(PREFACE.len());
+
+        let settings = match self.header_table_size {
+            Some(size) => Frame::settings(&[(Setting::HeaderTableSize, size)]),
+            None => Frame::settings(&[]),
+        };
+        let mut out = Vec::new();
+        settings.write(&mut out).await.unwrap();
+        self.stream.write_all(&out).await.unwrap();
+    }
+
+    /// Serves HTTP/2 once the client is about to send its preface. With an
+    /// upgrade, `upgraded` is the path of the request to answer on stream 1.
+    async fn run(mut self, upgraded: Option<String>) {
+        self.start().await;
+        if let Some(path) = upgraded {
+            self.respond(1, &path).await;
+        }
+        while let Some(frame) = self.read_frame().await {
+            match frame.frame_type {
+                // our settings only apply once the client acknowledged them
+                FrameType::Settings(flags) if flags.contains(SettingsFlags::Ack) => {
+                    if let Some(size) = self.header_table_size {
+                        self.decoder.set_max_table_size(size as usize);
+                    }
+                }
+                // requests have no body, and fit in one frame
+                FrameType::Headers(_) => {
+                    let block = self.decoder.decode(&frame.payload).unwrap();
+                    let (_, path) = block.iter().find(|(k, _)| k == b":path").unwrap();
+                    let path = String::from_utf8(path.clone()).unwrap();
+                    self.respond(frame.stream_id, &path).await;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Starts a server, which speaks HTTP/2 right away, or only after an HTTP/1.1
+/// upgrade
+async fn serve(upgrade: bool) -> std::net::SocketAddr {
+    serve_with(upgrade, None).await
+}
+
+async fn serve_with(upgrade: bool, header_table_size: Option<u32>) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut server = Server::new(stream);
+                server.header_table_size = header_table_size;
+                let upgraded = match upgrade {
+                    true => Some(server.accept_upgrade().await),
+                    false => None,
+                };
+                server.run(upgraded).await;
+            });
+        }
+    });
+    addr
+}
+
+fn request(path: &str) -> http::Request<()> {
+    http::Request::get(format!("http://localhost{path}"))
+        .body(())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn prior_knowledge() {
+    let addr = serve(false).await;
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut conn = ClientConnection::handshake(stream).await.unwrap();
+
+    for path in ["/", "/second", "/third"] {
+        let res = conn.send(&request(path)).await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "text/plain");
+        assert_eq!(res.body(), format!("hello from {path}").as_bytes());
+    }
+}
+
+#[tokio::test]
+async fn upgrade() {
+    let addr = serve(true).await;
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut conn, res) = ClientConnection::upgrade(stream, "localhost", "/upgrade")
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body(), b"hello from /upgrade");
+
+    // the next request goes on stream 3
+    let res = conn.send(&request("/after")).await.unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body(), b"hello from /after");
+}
+
+#[tokio::test]
+async fn smaller_header_table() {
+    // with a table of 0 bytes, the server can't remember headers from
+    // one request to the next: the client has to stop indexing headers
+    let addr = serve_with(false, Some(0)).await;
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut conn = ClientConnection::handshake(stream).await.unwrap();
+
+    for path in ["/", "/second", "/third"] {
+        // pseudo-headers are in the static table, this one isn't
+        let req = http::Request::get(format!("http://localhost{path}"))
+            .header("x-custom", "same every time")
+            .body(())
+            .unwrap();
+        let res = conn.send(&req).await.unwrap();
+        assert_eq!(res.body(), format!("hello from {path}").as_bytes());
+    }
+}
+
+/// Starts a server that answers the first request with `frames`, and returns
+/// the error code of the GOAWAY the client sends back, if any
+async fn misbehaving(
+    frames: fn(u32) -> Vec<Frame>,
+) -> (std::net::SocketAddr, JoinHandle<Option<u32>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut server = Server::new(stream);
+        server.start().await;
+
+        let stream_id = loop {
+            let frame = server.read_frame().await?;
+            if let FrameType::Headers(_) = frame.frame_type {
+                break frame.stream_id;
+            }
+        };
+        let mut out = Vec::new();
+        for frame in frames(stream_id) {
+            frame.write(&mut out).await.unwrap();
+        }
+        server.stream.write_all(&out).await.unwrap();
+
+        loop {
+            let frame = server.read_frame().await?;
+            if let FrameType::GoAway = frame.frame_type {
+                return Some(u32::from_be_bytes(frame.payload[4..8].try_into().unwrap()));
+            }
+        }
+    });
+    (addr, handle)
+}
+
+/// A header block with just `:status: 200`, split over a HEADERS and a
+/// CONTINUATION frame
+fn split_headers(stream_id: u32) -> (Frame, Frame) {
+    let mut block = HeaderBlock::default();
+    block.push(":status", "200");
+    let encoded = block.encode(&mut hpack::Encoder::new());
+
+    let mut headers = Frame::new(FrameType::Headers(Default::default()), stream_id);
+    headers.payload.extend_from_slice(&encoded[..1]);
+    let mut continuation = Frame::new(
+        FrameType::Continuation(ContinuationFlags::EndHeaders.into()),
+        stream_id,
+    );
+    continuation.payload.extend_from_slice(&encoded[1..]);
+    (headers, continuation)
+}
+
+#[tokio::test]
+async fn continuation() {
+    // the happy path first: split blocks are fine
+    let (addr, server) = misbehaving(|stream_id| {
+        let (headers, continuation) = split_headers(stream_id);
+        let mut data = Frame::new(FrameType::Data(DataFlags::EndStream.into()), stream_id);
+        data.payload.extend_from_slice(b"split");
+        vec![headers, continuation, data]
+    })
+    .await;
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut conn = ClientConnection::handshake(stream).await.unwrap();
+    let res = conn.send(&request("/")).await.unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body(), b"split");
+    drop(conn);
+    assert_eq!(server.await.unwrap(), None);
+
+    let cases: [fn(u32) -> Vec<Frame>; 3] = [
+        // a CONTINUATION out of nowhere
+        |stream_id| vec![split_headers(stream_id).1],
+        // on another stream than its HEADERS
+        |stream_id| {
+            let (headers, mut continuation) = split_headers(stream_id);
+            continuation.stream_id = stream_id + 2;
+            vec![headers, continuation]
+        },
+        // with another frame in between
+        |stream_id| {
+            let (headers, continuation) = split_headers(stream_id);
+            let mut ping = Frame::new(FrameType::Ping(Default::default()), 0);
+            ping.payload.extend_from_slice(&[0; 8]);
+            vec![headers, ping, continuation]
+        },
+    ];
+    for frames in cases {
+        let (addr, server) = misbehaving(frames).await;
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut conn = ClientConnection::handshake(stream).await.unwrap();
+        assert!(conn.send(&request("/")).await.is_err());
+        drop(conn);
+        // PROTOCOL_ERROR
+        assert_eq!(server.await.unwrap(), Some(0x1));
+    }
+}
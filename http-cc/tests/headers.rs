@@ -0,0 +1,107 @@
+use httplib::headers::{Header, HeaderError, HeaderMap};
+
+fn map<'a>(headers: &[(&'a str, &'a str)]) -> HeaderMap<'a> {
+    headers
+        .iter()
+        .map(|&(name, value)| Header {
+            name,
+            value: value.as_bytes(),
+        })
+        .collect()
+}
+
+#[test]
+fn content_length() {
+    assert_eq!(map(&[]).content_length(), Ok(None));
+    assert_eq!(
+        map(&[("Content-Length", "42")]).content_length(),
+        Ok(Some(42))
+    );
+
+    // repeated identical values are fine, on one line or several
+    let cl = map(&[("content-length", "5"), ("content-length", "5")]);
+    assert_eq!(cl.content_length(), Ok(Some(5)));
+    let cl = map(&[("content-length", "5, 5")]);
+    assert_eq!(cl.content_length(), Ok(Some(5)));
+
+    for conflicting in [
+        map(&[("content-length", "5"), ("content-length", "6")]),
+        map(&[("content-length", "5, 6")]),
+    ] {
+        assert_eq!(
+            conflicting.content_length(),
+            Err(HeaderError::ConflictingContentLength)
+        );
+    }
+
+    for invalid in [
+        "",
+        " , ",
+        "-1",
+        "+5",
+        "0x10",
+        "5 5",
+        // one more than u64::MAX
+        "18446744073709551616",
+    ] {
+        assert_eq!(
+            map(&[("content-length", invalid)]).content_length(),
+            Err(HeaderError::InvalidContentLength),
+            "{invalid:?}"
+        );
+    }
+    assert_eq!(
+        map(&[("content-length", "18446744073709551615")]).content_length(),
+        Ok(Some(u64::MAX))
+    );
+}
+
+#[test]
+fn get_all_keeps_the_order() {
+    let headers = map(&[
+        ("accept", "a"),
+        ("host", "example.org"),
+        ("Accept", "b, c"),
+        ("ACCEPT", "d"),
+    ]);
+    let all: Vec<_> = headers.get_all("accept").collect();
+    assert_eq!(all, [&b"a"[..], b"b, c", b"d"]);
+    let list: Vec<_> = headers.get_list("accept").collect();
+    assert_eq!(list, [&b"a"[..], b"b", b"c", b"d"]);
+    assert_eq!(headers.get("accept"), Some("a"));
+    assert_eq!(headers.get_all("missing").count(), 0);
+}
+
+#[test]
+fn transfer_encoding() {
+    let headers = map(&[
+        ("transfer-encoding", "gzip"),
+        ("Transfer-Encoding", " Chunked "),
+    ]);
+    assert_eq!(headers.transfer_encoding(), [&b"gzip"[..], b"Chunked"]);
+    assert!(headers.is_chunked());
+
+    // chunked has to be the last coding applied
+    let headers = map(&[("transfer-encoding", "chunked, gzip")]);
+    assert!(!headers.is_chunked());
+    assert!(!map(&[]).is_chunked());
+    assert!(map(&[]).transfer_encoding().is_empty());
+}
+
+#[test]
+fn connection() {
+    let headers = map(&[
+        ("connection", "Keep-Alive, upgrade"),
+        ("Connection", ",x-custom,"),
+    ]);
+    assert_eq!(
+        headers.connection(),
+        [&b"Keep-Alive"[..], b"upgrade", b"x-custom"]
+    );
+    assert!(headers.has_connection_option("keep-alive"));
+    assert!(headers.has_connection_option("UPGRADE"));
+    assert!(headers.has_connection_option("x-custom"));
+    assert!(!headers.has_connection_option("close"));
+    // options are whole list elements, not substrings
+    assert!(!map(&[("connection", "closed")]).has_connection_option("close"));
+}
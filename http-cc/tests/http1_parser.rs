@@ -0,0 +1,223 @@
+use httplib::{
+    http1::{
+        request, request_with_limits, response, response_with_limits, ParserLimits, RequestParser,
+        Version,
+    },
+    ParseError, ParseErrorKind,
+};
+
+#[test]
+fn parses_http1_minor_versions() {
+    let (_, res) = response(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+    assert_eq!(res.version, Version::Http10);
+    let (_, res) = response(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+    assert_eq!(res.version, Version::Http11);
+
+    // later minor versions are compatible with HTTP/1.1
+    let (_, res) = response(b"HTTP/1.2 200 OK\r\n\r\n").unwrap();
+    assert_eq!(res.version, Version::Http11);
+    let (_, req) = request(b"GET / HTTP/1.9\r\nhost: x\r\n\r\n").unwrap();
+    assert_eq!(req.version, Version::Http11);
+
+    for head in [
+        &b"HTTP/2.0 200 OK\r\n\r\n"[..],
+        b"HTTP/1.x 200 OK\r\n\r\n",
+        b"HTTP/1.10 200 OK\r\n\r\n",
+    ] {
+        let Err(nom::Err::Error(e) | nom::Err::Failure(e)) = response(head) else {
+            panic!("{:?} should be rejected", String::from_utf8_lossy(head));
+        };
+        assert_eq!(e.kind, ParseErrorKind::InvalidStatusLine);
+    }
+}
+
+#[test]
+fn lenient_status_lines() {
+    let (_, res) = response(b"HTTP/1.1 204\r\n\r\n").unwrap();
+    assert_eq!(res.status, 204);
+    assert_eq!(res.status_text, b"");
+
+    let (_, res) = response(b"HTTP/1.1 404 Not Found\r\n\r\n").unwrap();
+    assert_eq!(res.status, 404);
+    assert_eq!(res.status_text_str(), Some("Not Found"));
+}
+
+#[test]
+fn status_text_may_be_latin1() {
+    let (_, res) = response(b"HTTP/1.1 200 G\xe9n\xe9r\xe9\r\nserver: x\r\n\r\n").unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.status_text, b"G\xe9n\xe9r\xe9");
+    assert_eq!(res.status_text_str(), None);
+    assert_eq!(res.headers.get("server"), Some("x"));
+
+    // same thing, in pieces
+    let mut parser = httplib::http1::ResponseParser::new();
+    let input = b"HTTP/1.1 404 Introuvabl\xe9\r\nserver: x\r\n\r\n";
+    assert!(matches!(
+        parser.parse(&input[..30]),
+        Err(nom::Err::Incomplete(_))
+    ));
+    let (_, res) = parser.parse(input).unwrap();
+    assert_eq!(res.status_text, b"Introuvabl\xe9");
+}
+
+#[test]
+fn request_parser_resumes() {
+    let input = b"POST /upload HTTP/1.1\r\nhost: example.org\r\ncontent-length: 5\r\n\r\nhello";
+    let mut parser = RequestParser::new();
+    // fed one byte at a time, like a slow client would
+    for end in 0..input.len() - 5 {
+        assert!(
+            matches!(parser.parse(&input[..end]), Err(nom::Err::Incomplete(_))),
+            "{end}"
+        );
+    }
+    let (rest, req) = parser.parse(input).unwrap();
+    assert_eq!(rest, b"hello");
+    assert_eq!((req.method, req.target), ("POST", "/upload"));
+    assert_eq!(req.headers.get("host"), Some("example.org"));
+    assert_eq!(req.headers.len(), 2);
+
+    // it was reset, so it's ready for the next request
+    let (_, req) = parser.parse(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    assert_eq!(req.version, Version::Http10);
+    assert!(req.headers.is_empty());
+
+    let err = parser
+        .parse(b"GET / HTTP/1.1\r\nbad header\r\n")
+        .unwrap_err();
+    assert!(matches!(err, nom::Err::Error(e) if e.kind == ParseErrorKind::InvalidHeaderName));
+}
+
+fn error_kind<T: std::fmt::Debug>(res: nom::IResult<&[u8], T, ParseError>) -> ParseErrorKind {
+    match res {
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => e.kind,
+        res => panic!("expected an error, got {res:?}"),
+    }
+}
+
+#[test]
+fn max_headers() {
+    let limits = ParserLimits {
+        max_headers: 3,
+        ..Default::default()
+    };
+    let three = b"HTTP/1.1 200 OK\r\na: 1\r\nb: 2\r\nc: 3\r\n\r\n";
+    let (_, res) = response_with_limits(three, &limits).unwrap();
+    assert_eq!(res.headers.len(), 3);
+    let four = b"HTTP/1.1 200 OK\r\na: 1\r\nb: 2\r\nc: 3\r\nd: 4\r\n\r\n";
+    assert_eq!(
+        error_kind(response_with_limits(four, &limits)),
+        ParseErrorKind::TooManyHeaders
+    );
+
+    let three = b"GET / HTTP/1.1\r\nhost: x\r\nb: 2\r\nc: 3\r\n\r\n";
+    assert!(request_with_limits(three, &limits).is_ok());
+    // it fails as soon as the fourth line starts, complete or not
+    let four = b"GET / HTTP/1.1\r\nhost: x\r\nb: 2\r\nc: 3\r\nd";
+    assert_eq!(
+        error_kind(request_with_limits(four, &limits)),
+        ParseErrorKind::TooManyHeaders
+    );
+}
+
+#[test]
+fn max_head_len() {
+    let head = b"HTTP/1.1 200 OK\r\nserver: x\r\n\r\n";
+    let limits = ParserLimits {
+        max_head_len: head.len(),
+        ..Default::default()
+    };
+    assert!(response_with_limits(head, &limits).is_ok());
+
+    let limits = ParserLimits {
+        max_head_len: head.len() - 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        error_kind(response_with_limits(head, &limits)),
+        ParseErrorKind::HeadTooLarge
+    );
+
+    // a head that's still going past the limit won't fit either, there's no
+    // point in waiting for the rest of it
+    let limits = ParserLimits {
+        max_head_len: 32,
+        ..Default::default()
+    };
+    let endless = b"GET / HTTP/1.1\r\nhost: x\r\na: 1\r\nb: 2\r\nc: 3\r\n";
+    assert!(matches!(
+        request_with_limits(&endless[..30], &limits),
+        Err(nom::Err::Incomplete(_))
+    ));
+    assert_eq!(
+        error_kind(request_with_limits(endless, &limits)),
+        ParseErrorKind::HeadTooLarge
+    );
+    assert_eq!(
+        error_kind(RequestParser::with_limits(limits).parse(endless)),
+        ParseErrorKind::HeadTooLarge
+    );
+}
+
+#[test]
+fn max_status_text_len() {
+    let limits = ParserLimits {
+        max_status_text_len: 9,
+        ..Default::default()
+    };
+    let (_, res) = response_with_limits(b"HTTP/1.1 404 Not Found\r\n\r\n", &limits).unwrap();
+    assert_eq!(res.status_text, b"Not Found");
+    assert_eq!(
+        error_kind(response_with_limits(
+            b"HTTP/1.1 404 Not Foundd\r\n\r\n",
+            &limits
+        )),
+        ParseErrorKind::StatusTextTooLong
+    );
+
+    // without the end of the line: short enough to wait for more, or not
+    assert!(matches!(
+        response_with_limits(b"HTTP/1.1 404 Not Found", &limits),
+        Err(nom::Err::Incomplete(_))
+    ));
+    assert_eq!(
+        error_kind(response_with_limits(
+            b"HTTP/1.1 404 Not Found, or so",
+            &limits
+        )),
+        ParseErrorKind::StatusTextTooLong
+    );
+}
+
+#[test]
+fn max_header_line_len() {
+    let limits = ParserLimits {
+        max_header_line_len: 12,
+        ..Default::default()
+    };
+    // exactly 12 bytes, CRLF not included
+    let (_, res) =
+        response_with_limits(b"HTTP/1.1 200 OK\r\nserver: abcd\r\n\r\n", &limits).unwrap();
+    assert_eq!(res.headers.get("server"), Some("abcd"));
+
+    for head in [
+        &b"HTTP/1.1 200 OK\r\nserver: abcde\r\n\r\n"[..],
+        // no CRLF in sight
+        b"HTTP/1.1 200 OK\r\nserver: abcdefghijklmnop",
+    ] {
+        assert_eq!(
+            error_kind(response_with_limits(head, &limits)),
+            ParseErrorKind::HeaderTooLarge,
+            "{:?}",
+            String::from_utf8_lossy(head)
+        );
+    }
+    assert_eq!(
+        error_kind(request_with_limits(
+            b"GET / HTTP/1.1\r\nhost: example.org\r\n\r\n",
+            &limits
+        )),
+        ParseErrorKind::HeaderTooLarge
+    );
+}
@@ -0,0 +1,68 @@
+use httplib::{
+    http2::{Frame, FrameType, DEFAULT_MAX_FRAME_SIZE},
+    ParseErrorKind,
+};
+
+/// An ALTSVC frame (type 0xa) for stream 0, see
+/// https://www.rfc-editor.org/rfc/rfc7838#section-4, followed by a SETTINGS ack
+const ALTSVC: &[u8] = &[
+    0x00, 0x00, 0x05, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, // header
+    0x00, 0x00, b'h', b'3', b'=', // payload
+    0x00, 0x00, 0x00, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, // SETTINGS ack
+];
+
+#[test]
+fn unknown_frames_are_skipped() {
+    let (rest, frame) = Frame::parse(ALTSVC).unwrap();
+    assert!(matches!(frame.frame_type, FrameType::Unknown(0xa)));
+    assert_eq!(frame.payload.len(), 5);
+
+    // the payload was consumed, the next frame is a SETTINGS ack
+    let (rest, frame) = Frame::parse(rest).unwrap();
+    assert!(matches!(frame.frame_type, FrameType::Settings(flags) if !flags.is_empty()));
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn unknown_frames_wait_for_their_payload() {
+    assert!(matches!(
+        Frame::parse(&ALTSVC[..12]),
+        Err(nom::Err::Incomplete(_))
+    ));
+}
+
+#[test]
+fn strict_parsing_rejects_unknown_frames() {
+    let Err(nom::Err::Failure(e)) = Frame::parse_strict(ALTSVC) else {
+        panic!("unknown frame type should be rejected");
+    };
+    assert_eq!(e.kind, ParseErrorKind::UnknownFrameType(0xa));
+    assert_eq!(e.offset, 3);
+}
+
+#[tokio::test]
+async fn unknown_frames_round_trip() {
+    let mut frame = Frame::new(FrameType::Unknown(0xb), 0);
+    frame.payload.extend_from_slice(b"grease");
+    let mut out = Vec::new();
+    frame.write(&mut out).await.unwrap();
+
+    let (_, frame) = Frame::parse(&out).unwrap();
+    assert!(matches!(frame.frame_type, FrameType::Unknown(0xb)));
+    assert_eq!(&frame.payload[..], b"grease");
+}
+
+#[test]
+fn frames_too_large_are_rejected_early() {
+    let header = [0x00, 0x40, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+    let Err(nom::Err::Failure(e)) = Frame::parse(&header) else {
+        panic!("frame should be too large");
+    };
+    assert_eq!(
+        e.kind,
+        ParseErrorKind::FrameTooLarge {
+            len: DEFAULT_MAX_FRAME_SIZE + 1,
+            max: DEFAULT_MAX_FRAME_SIZE
+        }
+    );
+}
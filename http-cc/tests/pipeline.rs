@@ -0,0 +1,109 @@
+mod common;
+
+use common::{ok, scripted, serve, Step};
+use httplib::http1::{ClientConnection, PipelineError, RequestHead, ServerResponse};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+fn requests(n: usize) -> Vec<RequestHead> {
+    (0..n)
+        .map(|i| {
+            RequestHead::builder("GET", &format!("/{i}"))
+                .header("host", "localhost")
+                .build()
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Pipelines `n` requests, and returns the bodies of the responses, and the
+/// error that ended the pipeline if any
+async fn pipeline(addr: std::net::SocketAddr, n: usize) -> (Vec<String>, Option<PipelineError>) {
+    let reqs = requests(n);
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+    let mut pipeline = conn
+        .pipeline(reqs.iter().map(|req| (req, &[][..])))
+        .await
+        .unwrap();
+    assert_eq!(pipeline.remaining(), n);
+
+    let mut bodies = Vec::new();
+    while let Some(res) = pipeline.next().await {
+        match res {
+            Ok((_, mut body)) => {
+                let mut out = String::new();
+                body.read_to_string(&mut out).await.unwrap();
+                bodies.push(out);
+            }
+            Err(e) => {
+                assert!(pipeline.next().await.is_none(), "nothing after an error");
+                return (bodies, Some(e));
+            }
+        }
+    }
+    (bodies, None)
+}
+
+#[tokio::test]
+async fn responses_come_back_in_order() {
+    let addr = serve(|req: RequestHead, _| async move { ok(req.target()) }).await;
+    let (bodies, err) = pipeline(addr, 5).await;
+    assert_eq!(bodies, ["/0", "/1", "/2", "/3", "/4"]);
+    assert!(err.is_none());
+}
+
+#[tokio::test]
+async fn server_closing_midway_leaves_the_rest_unanswered() {
+    // answers 2 of the 5 requests, then hangs up without a word
+    let addr = scripted(|req| match req.target() {
+        "/0" => vec![Step::Write(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n/0",
+        )],
+        _ => vec![
+            Step::Write(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n/1"),
+            Step::Close,
+        ],
+    })
+    .await;
+    let (bodies, err) = pipeline(addr, 5).await;
+    assert_eq!(bodies, ["/0", "/1"]);
+    assert_eq!(err.unwrap().unanswered, 2..5);
+}
+
+#[tokio::test]
+async fn connection_close_leaves_the_rest_unanswered() {
+    // response 1 says it's the last one, so requests 2.. are never answered
+    let addr = serve(|req: RequestHead, _| async move {
+        let mut res = ServerResponse::builder(200).body(req.target());
+        if req.target() == "/1" {
+            res = res.header("connection", "close");
+        }
+        res.build().unwrap()
+    })
+    .await;
+    let (bodies, err) = pipeline(addr, 4).await;
+    assert_eq!(bodies, ["/0", "/1"]);
+    let err = err.unwrap();
+    assert_eq!(err.unanswered, 2..4);
+    assert!(err
+        .to_string()
+        .starts_with("requests 2..4 were never answered"));
+}
+
+#[tokio::test]
+async fn invalid_request_sends_nothing() {
+    let addr = serve(|req: RequestHead, _| async move { ok(req.target()) }).await;
+    let reqs = requests(3);
+    let mut conn = ClientConnection::new(TcpStream::connect(addr).await.unwrap());
+
+    // the last request has no framing for its body
+    let bodies = [&b""[..], b"", b"oops"];
+    let err = conn.pipeline(reqs.iter().zip(bodies)).await.err().unwrap();
+    assert_eq!(err.unanswered, 0..3);
+
+    // the connection is still usable, the first requests weren't sent
+    let (res, mut body) = conn.send(&reqs[1], b"").await.unwrap();
+    assert_eq!(res.status, 200);
+    let mut out = String::new();
+    body.read_to_string(&mut out).await.unwrap();
+    assert_eq!(out, "/1");
+}
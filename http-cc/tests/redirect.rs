@@ -0,0 +1,159 @@
+use httplib::redirect::{RedirectError, RedirectPolicy, Redirects};
+
+fn start(method: &str, url: &str) -> Redirects {
+    RedirectPolicy::default()
+        .start(method, url.parse().unwrap())
+        .unwrap()
+}
+
+#[test]
+fn relative_locations() {
+    let mut redirects = start("GET", "http://example.org/a/b?q=1");
+    for (location, expected) in [
+        ("c", "http://example.org/a/c"),
+        ("?page=2", "http://example.org/a/c?page=2"),
+        ("/d#fragment", "http://example.org/d"),
+        ("//example.net/e", "http://example.net/e"),
+        ("https://example.com/f", "https://example.com/f"),
+    ] {
+        assert!(redirects.follow(302, Some(location)).unwrap());
+        assert_eq!(redirects.url(), expected);
+    }
+    assert_eq!(redirects.hops(), 5);
+}
+
+#[test]
+fn not_redirects() {
+    let mut redirects = start("GET", "http://example.org/");
+    assert!(!redirects.follow(200, Some("/elsewhere")).unwrap());
+    assert!(!redirects.follow(304, Some("/elsewhere")).unwrap());
+    assert!(!redirects.follow(301, None).unwrap());
+    assert_eq!(redirects.url(), "http://example.org/");
+    assert_eq!(redirects.hops(), 0);
+}
+
+#[test]
+fn methods() {
+    // 307 and 308 keep the method and body
+    let mut redirects = start("POST", "http://example.org/");
+    assert!(redirects.follow(307, Some("/a")).unwrap());
+    assert_eq!(redirects.method(), "POST");
+    assert!(redirects.keep_body());
+
+    // a POST redirected by 301 or 302 becomes a GET, and stays one
+    assert!(redirects.follow(301, Some("/b")).unwrap());
+    assert_eq!(redirects.method(), "GET");
+    assert!(!redirects.keep_body());
+    assert!(redirects.follow(308, Some("/c")).unwrap());
+    assert_eq!(redirects.method(), "GET");
+    assert!(!redirects.keep_body());
+
+    // 303 turns anything but HEAD into a GET
+    let mut redirects = start("PUT", "http://example.org/");
+    assert!(redirects.follow(303, Some("/a")).unwrap());
+    assert_eq!(redirects.method(), "GET");
+    let mut redirects = start("HEAD", "http://example.org/");
+    assert!(redirects.follow(303, Some("/a")).unwrap());
+    assert_eq!(redirects.method(), "HEAD");
+    assert!(redirects.keep_body());
+}
+
+#[test]
+fn credentials_are_stripped_across_origins() {
+    let policy = RedirectPolicy::default().sensitive_header("X-Api-Key");
+    let mut redirects = policy
+        .start("GET", "https://example.org/".parse().unwrap())
+        .unwrap();
+    let sensitive = [
+        "authorization",
+        "Cookie",
+        "proxy-authorization",
+        "x-api-key",
+    ];
+
+    // same origin, even with the default port spelled out
+    assert!(redirects
+        .follow(302, Some("https://EXAMPLE.org:443/a"))
+        .unwrap());
+    assert!(sensitive.iter().all(|h| redirects.keep_header(h)));
+
+    // another scheme is another origin
+    assert!(redirects.follow(302, Some("http://example.org/a")).unwrap());
+    assert!(!sensitive.iter().any(|h| redirects.keep_header(h)));
+    assert!(redirects.keep_header("accept"));
+
+    // coming back doesn't make the credentials safe to send again: the
+    // other origin chose where we went
+    assert!(redirects
+        .follow(302, Some("https://example.org/b"))
+        .unwrap());
+    assert!(!redirects.keep_header("authorization"));
+
+    // and neither do other ports or hosts
+    for url in ["https://example.org:8443/", "https://api.example.org/"] {
+        let mut redirects = start("GET", "https://example.org/");
+        assert!(redirects.follow(307, Some(url)).unwrap());
+        assert!(!redirects.keep_header("authorization"));
+    }
+}
+
+#[test]
+fn loops_are_detected() {
+    let mut redirects = start("GET", "http://example.org/a");
+    assert!(redirects.follow(302, Some("/b")).unwrap());
+    assert_eq!(
+        redirects.follow(302, Some("/a")),
+        Err(RedirectError::Loop("http://example.org/a".into()))
+    );
+
+    // the same URL with another method isn't a loop: that's how
+    // POST-redirect-GET works
+    let mut redirects = start("POST", "http://example.org/form");
+    assert!(redirects.follow(303, Some("/form")).unwrap());
+    assert_eq!(redirects.method(), "GET");
+    assert!(matches!(
+        redirects.follow(303, Some("/form")),
+        Err(RedirectError::Loop(_))
+    ));
+}
+
+#[test]
+fn max_hops() {
+    let mut redirects = start("GET", "http://example.org/0");
+    for i in 1..=10 {
+        assert!(redirects.follow(302, Some(&format!("/{i}"))).unwrap());
+    }
+    assert_eq!(
+        redirects.follow(302, Some("/11")),
+        Err(RedirectError::TooManyRedirects(10))
+    );
+    // the next request is still the last one we were sent to
+    assert_eq!(redirects.url(), "http://example.org/10");
+
+    let policy = RedirectPolicy::default().max_hops(0);
+    let mut redirects = policy
+        .start("GET", "http://example.org/".parse().unwrap())
+        .unwrap();
+    assert_eq!(
+        redirects.follow(302, Some("/a")),
+        Err(RedirectError::TooManyRedirects(0))
+    );
+    // but non-redirects are still fine
+    assert!(!redirects.follow(200, None).unwrap());
+}
+
+#[test]
+fn invalid_urls() {
+    assert!(matches!(
+        RedirectPolicy::default().start("GET", "/relative".parse().unwrap()),
+        Err(RedirectError::InvalidUrl(_))
+    ));
+
+    let mut redirects = start("GET", "http://example.org/");
+    for location in ["ftp://example.org/", "mailto:bear@example.org", "http://"] {
+        assert!(matches!(
+            redirects.follow(302, Some(location)),
+            Err(RedirectError::InvalidUrl(_))
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use httplib::http1::{Framing, InvalidHead, RequestHead, Version};
+use proptest::prelude::*;
+
+fn token() -> impl Strategy<Value = String> {
+    "[!#$%&'*+.^_`|~0-9A-Za-z-]{1,16}"
+}
+
+fn framing() -> impl Strategy<Value = Framing> {
+    prop_oneof![
+        Just(Framing::None),
+        any::<u64>().prop_map(Framing::ContentLength),
+        Just(Framing::Chunked),
+    ]
+}
+
+fn headers() -> impl Strategy<Value = Vec<(String, Vec<u8>)>> {
+    // values don't start or end with whitespace, since it's trimmed
+    let value = proptest::collection::vec(
+        prop_oneof![0x21u8..=0x7e, 0x80u8..=0xff, Just(b' '), Just(b'\t')],
+        0..32,
+    )
+    .prop_map(|v| {
+        let trim = |c: &u8| *c != b' ' && *c != b'\t';
+        match (v.iter().position(trim), v.iter().rposition(trim)) {
+            (Some(start), Some(end)) => v[start..=end].to_vec(),
+            _ => vec![],
+        }
+    });
+    let name = token().prop_filter("framing headers are set separately", |name| {
+        !name.eq_ignore_ascii_case("content-length")
+            && !name.eq_ignore_ascii_case("transfer-encoding")
+    });
+    proptest::collection::vec((name, value), 0..8)
+}
+
+proptest! {
+    #[test]
+    fn round_trip(
+        method in token(),
+        target in "[!-~]{1,64}",
+        http10 in any::<bool>(),
+        headers in headers(),
+        framing in framing(),
+    ) {
+        let version = if http10 { Version::Http10 } else { Version::Http11 };
+        let mut builder = RequestHead::builder(&method, &target)
+            .version(version)
+            .framing(framing);
+        for (name, value) in &headers {
+            builder = builder.header(name, value);
+        }
+        // HTTP/1.0 has no chunked encoding, so that wouldn't parse back
+        if http10 && framing == Framing::Chunked {
+            prop_assert_eq!(builder.build(), Err(InvalidHead::ChunkedHttp10));
+            return Ok(());
+        }
+        let head = builder.build().unwrap();
+
+        let mut out = Vec::new();
+        head.encode(&mut out);
+        out.extend_from_slice(b"body");
+        let (rest, parsed) = RequestHead::parse(&out).unwrap();
+        prop_assert_eq!(rest, b"body");
+        prop_assert_eq!(parsed, head);
+    }
+
+    #[test]
+    fn control_characters_are_rejected(
+        name in token(),
+        prefix in "[a-z]{0,8}",
+        c in prop_oneof![Just(b'\r'), Just(b'\n'), Just(b'\0'), 0x01u8..0x09, Just(0x7f)],
+        suffix in "[a-z]{1,8}",
+    ) {
+        let mut value = prefix.into_bytes();
+        value.push(c);
+        value.extend_from_slice(suffix.as_bytes());
+
+        let res = RequestHead::builder("GET", "/").header(&name, &value).build();
+        let is_framing = name.eq_ignore_ascii_case("content-length")
+            || name.eq_ignore_ascii_case("transfer-encoding");
+        if is_framing {
+            prop_assert_eq!(res, Err(InvalidHead::FramingHeader(name)));
+        } else {
+            prop_assert_eq!(res, Err(InvalidHead::InvalidHeaderValue(name)));
+        }
+    }
+}
+
+#[test]
+fn header_injection_is_rejected() {
+    let res = RequestHead::builder("GET", "/")
+        .header("x-forwarded-for", "1.2.3.4\r\nhost: evil.example")
+        .build();
+    assert_eq!(
+        res,
+        Err(InvalidHead::InvalidHeaderValue("x-forwarded-for".into()))
+    );
+    assert!(matches!(
+        RequestHead::builder("GET", "/")
+            .header("x\r\ny", "z")
+            .build(),
+        Err(InvalidHead::InvalidHeaderName(_))
+    ));
+    assert!(matches!(
+        RequestHead::builder("GET", "/a b").build(),
+        Err(InvalidHead::InvalidTarget(_))
+    ));
+    assert!(matches!(
+        RequestHead::builder("GET\0", "/").build(),
+        Err(InvalidHead::InvalidMethod(_))
+    ));
+}
+
+#[test]
+fn framing_headers_are_rejected() {
+    for name in ["content-length", "Transfer-Encoding"] {
+        let res = RequestHead::builder("POST", "/").header(name, "4").build();
+        assert_eq!(res, Err(InvalidHead::FramingHeader(name.into())));
+    }
+    assert_eq!(
+        RequestHead::builder("POST", "/")
+            .framing(Framing::CloseDelimited)
+            .build(),
+        Err(InvalidHead::CloseDelimited)
+    );
+}
+
+#[test]
+fn chunked_http10_is_rejected() {
+    // whichever is set first
+    let res = RequestHead::builder("POST", "/")
+        .framing(Framing::Chunked)
+        .version(Version::Http10)
+        .build();
+    assert_eq!(res, Err(InvalidHead::ChunkedHttp10));
+    let res = RequestHead::builder("POST", "/")
+        .version(Version::Http10)
+        .framing(Framing::Chunked)
+        .build();
+    assert_eq!(res, Err(InvalidHead::ChunkedHttp10));
+
+    let res = RequestHead::builder("POST", "/")
+        .version(Version::Http10)
+        .framing(Framing::ContentLength(5))
+        .build();
+    assert!(res.is_ok());
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use httplib::http1::{Framing, InvalidResponse, RequestHead, Server, ServerResponse};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Starts a server that answers `<method> <target> <body>`, and returns the
+/// requests its handler saw, in the same format
+async fn serve() -> (std::net::SocketAddr, Arc<Mutex<Vec<String>>>) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let handler = {
+        let seen = seen.clone();
+        move |req: RequestHead, body: Vec<u8>| {
+            let line = format!(
+                "{} {} {}",
+                req.method(),
+                req.target(),
+                String::from_utf8_lossy(&body)
+            );
+            seen.lock().unwrap().push(line.clone());
+            let close = req.target() == "/close";
+            async move {
+                let mut res = ServerResponse::builder(200).body(line);
+                if close {
+                    res = res.header("connection", "close");
+                }
+                res.build().unwrap()
+            }
+        }
+    };
+    let mut server = Server::bind("127.0.0.1:0", handler).await.unwrap();
+    server.set_max_body_len(16);
+    (common::spawn(server), seen)
+}
+
+/// Sends raw bytes, and reads until the server closes the connection
+async fn exchange(addr: std::net::SocketAddr, req: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(req).await.unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).await.unwrap();
+    out
+}
+
+#[tokio::test]
+async fn keep_alive() {
+    let (addr, seen) = serve().await;
+    let res = exchange(
+        addr,
+        b"GET /a HTTP/1.1\r\nhost: localhost\r\n\r\n\
+          POST /b HTTP/1.1\r\nhost: localhost\r\ncontent-length: 5\r\n\r\nhello\
+          GET /close HTTP/1.1\r\nhost: localhost\r\n\r\n",
+    )
+    .await;
+
+    assert_eq!(res.matches("HTTP/1.1 200 OK\r\n").count(), 3);
+    assert!(res.ends_with("\r\n\r\nGET /close "));
+    // the handler said it already, so it isn't repeated
+    assert_eq!(res.matches("connection: close\r\n").count(), 1, "{res}");
+    assert_eq!(
+        *seen.lock().unwrap(),
+        ["GET /a ", "POST /b hello", "GET /close "]
+    );
+}
+
+#[tokio::test]
+async fn chunked_body() {
+    let (addr, seen) = serve().await;
+    let res = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\n\
+          connection: close\r\n\r\n\
+          5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+    )
+    .await;
+
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(*seen.lock().unwrap(), ["POST / hello world"]);
+}
+
+#[tokio::test]
+async fn expect_continue() {
+    let (addr, seen) = serve().await;
+    let res = exchange(
+        addr,
+        b"PUT / HTTP/1.1\r\nhost: localhost\r\nexpect: 100-continue\r\n\
+          content-length: 2\r\nconnection: close\r\n\r\nhi",
+    )
+    .await;
+
+    assert!(res.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n"));
+    assert_eq!(*seen.lock().unwrap(), ["PUT / hi"]);
+}
+
+#[tokio::test]
+async fn bad_requests_are_rejected() {
+    let (addr, seen) = serve().await;
+
+    let res = exchange(addr, b"GET / HTTP/1.1\r\n\r\n").await;
+    assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{res}");
+    assert!(res.contains("connection: close\r\n"));
+
+    let res = exchange(addr, b"GET / HTTP/1.1\r\nhost localhost\r\n\r\n").await;
+    assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{res}");
+
+    let res = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 17\r\n\r\n",
+    )
+    .await;
+    assert!(
+        res.starts_with("HTTP/1.1 413 Payload Too Large\r\n"),
+        "{res}"
+    );
+
+    let res = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\n\r\n\
+          11\r\n0123456789abcdefg\r\n0\r\n\r\n",
+    )
+    .await;
+    assert!(
+        res.starts_with("HTTP/1.1 413 Payload Too Large\r\n"),
+        "{res}"
+    );
+
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn request_smuggling_is_rejected() {
+    // a proxy going by content-length would forward a single request, with
+    // a body of `0\r\n\r\nGET /smuggled ...`. Read as chunked, that's two
+    // requests.
+    let (addr, seen) = serve().await;
+    let res = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 42\r\n\
+          transfer-encoding: chunked\r\n\r\n\
+          0\r\n\r\nGET /smuggled HTTP/1.1\r\nhost: localhost\r\n\r\n",
+    )
+    .await;
+
+    assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{res}");
+    assert_eq!(res.matches("HTTP/1.1").count(), 1, "{res}");
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[test]
+fn ambiguous_framing_fails_to_parse() {
+    let req = b"POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 3\r\n\
+                transfer-encoding: chunked\r\n\r\n";
+    assert!(RequestHead::parse(req).is_err());
+
+    let req = b"POST / HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\n\r\n";
+    let (_, head) = RequestHead::parse(req).unwrap();
+    assert_eq!(head.framing(), Framing::Chunked);
+}
+
+#[test]
+fn invalid_responses() {
+    for status in [100, 101, 600, 42] {
+        assert_eq!(
+            ServerResponse::builder(status).build(),
+            Err(InvalidResponse::InvalidStatus(status))
+        );
+    }
+    assert_eq!(
+        ServerResponse::builder(200)
+            .header("content-length", "3")
+            .build(),
+        Err(InvalidResponse::FramingHeader("content-length".into()))
+    );
+    assert_eq!(
+        ServerResponse::builder(200)
+            .header("x-evil", "a\r\nset-cookie: b")
+            .build(),
+        Err(InvalidResponse::InvalidHeaderValue("x-evil".into()))
+    );
+    assert_eq!(
+        ServerResponse::builder(200).header("bad name", "a").build(),
+        Err(InvalidResponse::InvalidHeaderName("bad name".into()))
+    );
+}
+
+#[tokio::test]
+async fn slow_clients() {
+    // the head arrives a few bytes at a time
+    let (addr, seen) = serve().await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let req = b"POST /slow HTTP/1.1\r\nhost: localhost\r\ncontent-length: 2\r\n\
+                connection: close\r\n\r\nhi";
+    for piece in req.chunks(3) {
+        stream.write_all(piece).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+    }
+    let mut res = String::new();
+    stream.read_to_string(&mut res).await.unwrap();
+
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{res}");
+    assert_eq!(*seen.lock().unwrap(), ["POST /slow hi"]);
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{ok, scripted, serve, Step};
+use httplib::{
+    http1::{ClientConnection, Framing, RequestHead},
+    timings::RequestTimings,
+};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+const DELAY: Duration = Duration::from_millis(50);
+
+async fn connect(addr: std::net::SocketAddr) -> ClientConnection<TcpStream> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    ClientConnection::new(stream)
+}
+
+fn get() -> RequestHead {
+    RequestHead::builder("GET", "/")
+        .header("host", "localhost")
+        .build()
+        .unwrap()
+}
+
+fn put_expecting_continue() -> RequestHead {
+    RequestHead::builder("PUT", "/")
+        .header("host", "localhost")
+        .header("expect", "100-continue")
+        .framing(Framing::ContentLength(5))
+        .build()
+        .unwrap()
+}
+
+async fn read_body(conn: &mut ClientConnection<TcpStream>, req: &RequestHead, body: &[u8]) {
+    let (_, mut body) = conn.send(req, body).await.unwrap();
+    body.read_to_end(&mut Vec::new()).await.unwrap();
+}
+
+#[tokio::test]
+async fn phases() {
+    let addr = scripted(|_| {
+        vec![
+            Step::Sleep(DELAY),
+            Step::Write(b"HTTP/1.1 200 OK\r\n"),
+            Step::Sleep(DELAY),
+            Step::Write(b"content-length: 5\r\n\r\nhel"),
+            Step::Sleep(DELAY),
+            Step::Write(b"lo"),
+        ]
+    })
+    .await;
+    let mut conn = connect(addr).await;
+    let setup = RequestTimings {
+        dns_lookup: Some(Duration::from_millis(1)),
+        tcp_connect: Some(Duration::from_millis(2)),
+        // not a setup phase, so it's dropped
+        response_body: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    conn.set_connect_timings(setup);
+    read_body(&mut conn, &get(), &[]).await;
+
+    let timings = conn.timings();
+    assert_eq!(timings.dns_lookup, setup.dns_lookup);
+    assert_eq!(timings.tcp_connect, setup.tcp_connect);
+    assert_eq!(timings.tls_handshake, None);
+    let send = timings.request_send.unwrap();
+    let ttfb = timings.time_to_first_byte.unwrap();
+    let head = timings.response_head.unwrap();
+    let body = timings.response_body.unwrap();
+    assert!(send < DELAY, "{timings:?}");
+    assert!(ttfb >= DELAY && ttfb >= send, "{timings:?}");
+    assert!(head >= DELAY && head < DELAY * 2, "{timings:?}");
+    assert!(body >= DELAY && body < DELAY * 2, "{timings:?}");
+}
+
+#[tokio::test]
+async fn setup_is_only_reported_once() {
+    let addr = serve(|_, _| async { ok("") }).await;
+    let mut conn = connect(addr).await;
+    conn.set_connect_timings(RequestTimings {
+        tcp_connect: Some(Duration::from_millis(2)),
+        ..Default::default()
+    });
+
+    read_body(&mut conn, &get(), &[]).await;
+    assert!(conn.timings().tcp_connect.is_some());
+    read_body(&mut conn, &get(), &[]).await;
+    assert_eq!(conn.timings().tcp_connect, None);
+    assert!(conn.timings().time_to_first_byte.is_some());
+}
+
+#[tokio::test]
+async fn continue_is_not_the_first_byte() {
+    // the server sends the `100 Continue` itself, reads the body, then the
+    // handler takes its time
+    let addr = serve(|_, _| async {
+        tokio::time::sleep(DELAY).await;
+        ok("")
+    })
+    .await;
+    let mut conn = connect(addr).await;
+    read_body(&mut conn, &put_expecting_continue(), b"hello").await;
+
+    let timings = conn.timings();
+    let send = timings.request_send.unwrap();
+    let ttfb = timings.time_to_first_byte.unwrap();
+    // the final response came a delay after the body was sent
+    assert!(ttfb >= send + DELAY, "{timings:?}");
+}
+
+#[tokio::test]
+async fn response_buffered_with_continue() {
+    // the final response comes with the `100 Continue`, before the body was
+    // even sent: it can't count as arriving earlier than that
+    let addr = scripted(|_| {
+        vec![
+            Step::Write(
+                b"HTTP/1.1 100 Continue\r\n\r\n\
+                  HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n",
+            ),
+            Step::ReadBody(5),
+        ]
+    })
+    .await;
+    let mut conn = connect(addr).await;
+    read_body(&mut conn, &put_expecting_continue(), b"hello").await;
+
+    let timings = conn.timings();
+    let send = timings.request_send.unwrap();
+    let ttfb = timings.time_to_first_byte.unwrap();
+    assert!(ttfb >= send, "{timings:?}");
+    assert!(timings.response_head.is_some(), "{timings:?}");
+}
+
+#[tokio::test]
+async fn early_final_response() {
+    // the server doesn't want the body: sending the request was just
+    // sending its head
+    let addr = scripted(|_| {
+        vec![
+            Step::Sleep(DELAY),
+            Step::Write(b"HTTP/1.1 413 Payload Too Large\r\ncontent-length: 0\r\n\r\n"),
+        ]
+    })
+    .await;
+    let mut conn = connect(addr).await;
+    let (res, _) = conn
+        .send(&put_expecting_continue(), b"hello")
+        .await
+        .unwrap();
+    assert_eq!(res.status, 413);
+
+    let timings = conn.timings();
+    let send = timings.request_send.unwrap();
+    let ttfb = timings.time_to_first_byte.unwrap();
+    assert!(send < DELAY, "{timings:?}");
+    assert!(ttfb >= DELAY, "{timings:?}");
+}
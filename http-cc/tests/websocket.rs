@@ -0,0 +1,188 @@
+mod common;
+
+use bytes::BytesMut;
+use httplib::websocket::{self, accept_key, CloseCode, Frame, Message, Opcode, Role, WebSocket};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Accepts one connection and does the server side of the opening handshake.
+/// Returns the stream and whatever was read past the request.
+async fn accept(listener: &TcpListener) -> (TcpStream, BytesMut) {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut buf = BytesMut::new();
+    let head = common::read_head(&mut stream, &mut buf)
+        .await
+        .expect("client hung up during the handshake");
+    let key = head.headers().get("sec-websocket-key").unwrap();
+
+    let res = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         upgrade: websocket\r\n\
+         connection: upgrade\r\n\
+         sec-websocket-accept: {}\r\n\r\n",
+        accept_key(key)
+    );
+    stream.write_all(res.as_bytes()).await.unwrap();
+    (stream, buf)
+}
+
+/// Sends every text and binary message back, until the client closes
+async fn echo_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, leftover) = accept(&listener).await;
+            tokio::spawn(async move {
+                let mut ws = WebSocket::new(stream, leftover, Role::Server);
+                while let Some(msg) = ws.recv().await.unwrap() {
+                    if let Message::Text(_) | Message::Binary(_) = msg {
+                        ws.send(msg).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+async fn connect(addr: std::net::SocketAddr) -> WebSocket<TcpStream> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    websocket::connect(stream, "localhost", "/").await.unwrap()
+}
+
+#[tokio::test]
+async fn echo() {
+    let addr = echo_server().await;
+    let mut ws = connect(addr).await;
+
+    ws.send(Message::Text("hello".into())).await.unwrap();
+    assert_eq!(
+        ws.recv().await.unwrap(),
+        Some(Message::Text("hello".into()))
+    );
+
+    ws.send(Message::Binary(vec![0, 1, 2, 255])).await.unwrap();
+    assert_eq!(
+        ws.recv().await.unwrap(),
+        Some(Message::Binary(vec![0, 1, 2, 255]))
+    );
+
+    ws.send(Message::Text(String::new())).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Text(String::new())));
+
+    ws.close(CloseCode::Normal.repr(), "bye").await.unwrap();
+}
+
+#[tokio::test]
+async fn fragmented_messages() {
+    let addr = echo_server().await;
+    let mut ws = connect(addr).await;
+    ws.set_fragment_size(3);
+
+    let text = "a message split in several frames, with some ünïcödé";
+    ws.send(Message::Text(text.into())).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Text(text.into())));
+
+    let data: Vec<u8> = (0..=255).collect();
+    ws.send(Message::Binary(data.clone())).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Binary(data)));
+
+    ws.close(CloseCode::Normal.repr(), "").await.unwrap();
+}
+
+#[tokio::test]
+async fn ping_is_answered() {
+    let addr = echo_server().await;
+    let mut ws = connect(addr).await;
+
+    ws.send(Message::Ping(b"are you there".to_vec()))
+        .await
+        .unwrap();
+    assert_eq!(
+        ws.recv().await.unwrap(),
+        Some(Message::Pong(b"are you there".to_vec()))
+    );
+    ws.close(CloseCode::Normal.repr(), "").await.unwrap();
+}
+
+#[tokio::test]
+async fn close_handshake() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, leftover) = accept(&listener).await;
+        let mut ws = WebSocket::new(stream, leftover, Role::Server);
+        let close = ws.recv().await.unwrap();
+        // the close frame was echoed already, so we're done
+        assert_eq!(ws.recv().await.unwrap(), None);
+        close
+    });
+
+    let mut ws = connect(addr).await;
+    ws.close(CloseCode::GoingAway.repr(), "bye").await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), None);
+
+    assert_eq!(
+        server.await.unwrap(),
+        Some(Message::Close(Some((1001, "bye".into()))))
+    );
+}
+
+#[tokio::test]
+async fn invalid_close_code_is_a_protocol_error() {
+    for code in [0, 999, 1004, 1005, 1006, 1015, 2000, 5000] {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = accept(&listener).await;
+            let mut out = Vec::new();
+            Frame::close(code, "").encode(&mut out);
+            stream.write_all(&out).await.unwrap();
+
+            // the client should answer with its own close frame
+            let mut buf = Vec::new();
+            loop {
+                if let Ok((_, frame)) = Frame::parse(&buf) {
+                    return frame;
+                }
+                let n = stream.read_buf(&mut buf).await.unwrap();
+                assert_ne!(n, 0, "client hung up without a close frame");
+            }
+        });
+
+        let mut ws = connect(addr).await;
+        assert!(ws.recv().await.is_err(), "close code {code} was accepted");
+
+        let frame = server.await.unwrap();
+        assert_eq!(frame.opcode, Opcode::Close);
+        assert_eq!(
+            frame.payload[..2],
+            CloseCode::ProtocolError.repr().to_be_bytes()
+        );
+    }
+}
+
+#[tokio::test]
+async fn invalid_close_code_is_not_sent() {
+    let addr = echo_server().await;
+    let mut ws = connect(addr).await;
+
+    for code in [999, 1005, 1006, 1015] {
+        assert!(ws.close(code, "").await.is_err());
+        assert!(ws
+            .send(Message::Close(Some((code, String::new()))))
+            .await
+            .is_err());
+    }
+
+    // nothing was sent, so the connection still works
+    ws.send(Message::Text("still here".into())).await.unwrap();
+    assert_eq!(
+        ws.recv().await.unwrap(),
+        Some(Message::Text("still here".into()))
+    );
+    ws.close(4000, "app specific").await.unwrap();
+}